@@ -18,13 +18,15 @@
 //! Also includes pk, and pkh descriptors
 //!
 
-use std::{fmt, str::FromStr};
+use std::{fmt, str::FromStr, sync::Arc};
 
 use elements::secp256k1_zkp;
 use elements::{self, script, Script};
 
 use expression::{self, FromTree};
 use miniscript::context::ScriptContext;
+use miniscript::decode::Terminal;
+use miniscript::satisfy::elementssig_to_rawsig_preferred;
 use policy::{semantic, Liftable};
 use util::{varint_len, witness_to_scriptsig};
 use {
@@ -33,13 +35,13 @@ use {
 };
 
 use super::{
-    checksum::{desc_checksum, verify_checksum},
+    checksum::{verify_checksum, write_with_checksum},
     DescriptorTrait, ElementsTrait, ELMTS_STR,
 };
 
 /// Create a Bare Descriptor. That is descriptor that is
 /// not wrapped in sh or wsh. This covers the Pk descriptor
-#[derive(Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct Bare<Pk: MiniscriptKey> {
     /// underlying miniscript
     ms: Miniscript<Pk, BareCtx>,
@@ -73,8 +75,7 @@ impl<Pk: MiniscriptKey> fmt::Debug for Bare<Pk> {
 impl<Pk: MiniscriptKey> fmt::Display for Bare<Pk> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let desc = format!("{}{}", ELMTS_STR, self.ms);
-        let checksum = desc_checksum(&desc).map_err(|_| fmt::Error)?;
-        write!(f, "{}#{}", &desc, &checksum)
+        write_with_checksum(f, &desc)
     }
 }
 
@@ -120,7 +121,7 @@ where
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let desc_str = verify_checksum(s)?;
-        let top = expression::Tree::from_str(&desc_str[2..])?;
+        let top = expression::Tree::from_str(desc_str)?;
         Self::from_tree(&top)
     }
 }
@@ -233,7 +234,7 @@ impl<P: MiniscriptKey, Q: MiniscriptKey> TranslatePk<P, Q> for Bare<P> {
 }
 
 /// A bare PkH descriptor at top level
-#[derive(Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct Pkh<Pk: MiniscriptKey> {
     /// underlying publickey
     pk: Pk,
@@ -255,6 +256,17 @@ impl<Pk: MiniscriptKey> Pkh<Pk> {
     pub fn into_inner(self) -> Pk {
         self.pk
     }
+
+    /// The equivalent `c:pk_h` miniscript, which encodes to the same
+    /// script as this descriptor. Allows analysing `pkh` and bare
+    /// descriptors alike.
+    pub fn as_miniscript(&self) -> Miniscript<Pk, BareCtx> {
+        let pk_h =
+            Miniscript::from_ast(Terminal::PkH(self.pk.to_pubkeyhash())).expect("pk_h type checks");
+        let ms = Miniscript::from_ast(Terminal::Check(Arc::new(pk_h))).expect("c:pk_h type checks");
+        BareCtx::check_local_validity(&ms).expect("c:pk_h is valid in bare context");
+        ms
+    }
 }
 
 impl<Pk: MiniscriptKey> fmt::Debug for Pkh<Pk> {
@@ -266,8 +278,7 @@ impl<Pk: MiniscriptKey> fmt::Debug for Pkh<Pk> {
 impl<Pk: MiniscriptKey> fmt::Display for Pkh<Pk> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let desc = format!("{}pkh({})", ELMTS_STR, self.pk);
-        let checksum = desc_checksum(&desc).map_err(|_| fmt::Error)?;
-        write!(f, "{}#{}", &desc, &checksum)
+        write_with_checksum(f, &desc)
     }
 }
 
@@ -378,17 +389,27 @@ impl<Pk: MiniscriptKey> DescriptorTrait<Pk> for Pkh<Pk> {
         Pk: ToPublicKey,
         S: Satisfier<Pk>,
     {
-        if let Some(sig) = satisfier.lookup_sig(&self.pk) {
-            let mut sig_vec = sig.0.serialize_der().to_vec();
-            sig_vec.push(sig.1.as_u32() as u8);
+        let pk = self.pk.to_public_key();
+        // Signers may only know the signature by the hash of the key,
+        // in which case the satisfier also provides the key.
+        let sig = satisfier
+            .lookup_sig(&self.pk)
+            .and_then(|sig| elementssig_to_rawsig_preferred(&satisfier, &sig))
+            .or_else(|| {
+                satisfier
+                    .lookup_pkh_sig(&self.pk.to_pubkeyhash())
+                    .filter(|&(found_pk, _)| found_pk == pk)
+                    .and_then(|(_, sig)| elementssig_to_rawsig_preferred(&satisfier, &sig))
+            });
+        if let Some(sig_vec) = sig {
             let script_sig = script::Builder::new()
                 .push_slice(&sig_vec[..])
-                .push_key(&self.pk.to_public_key())
+                .push_key(&pk)
                 .into_script();
             let witness = vec![];
             Ok((witness, script_sig))
         } else {
-            Err(Error::MissingSig(self.pk.to_public_key()))
+            Err(Error::MissingSig(pk))
         }
     }
 
@@ -430,3 +451,25 @@ impl<P: MiniscriptKey, Q: MiniscriptKey> TranslatePk<P, Q> for Pkh<P> {
         Ok(Pkh::new(translatefpk(&self.pk)?))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::PublicKey;
+    use Descriptor;
+
+    #[test]
+    fn bare_from_str() {
+        // from_tree strips the `el` prefix itself, so from_str must not
+        let s = "elpk(020000000000000000000000000000000000000000000000000000000000000002)";
+        let bare = Bare::<PublicKey>::from_str(s).unwrap();
+        assert_eq!(
+            Descriptor::Bare(bare.clone()),
+            Descriptor::<PublicKey>::from_str(s).unwrap()
+        );
+        assert_eq!(
+            Bare::<PublicKey>::from_str(&bare.to_string()).unwrap(),
+            bare
+        );
+    }
+}
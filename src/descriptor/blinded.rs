@@ -28,7 +28,7 @@ use policy::{semantic, Liftable};
 use {Error, MiniscriptKey, Satisfier, ToPublicKey};
 
 use super::{
-    checksum::{desc_checksum, strip_checksum, verify_checksum},
+    checksum::{verify_checksum, write_with_checksum},
     Descriptor, DescriptorTrait, ElementsTrait, TranslatePk,
 };
 
@@ -74,11 +74,8 @@ impl<Pk: MiniscriptKey> fmt::Debug for Blinded<Pk> {
 
 impl<Pk: MiniscriptKey> fmt::Display for Blinded<Pk> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        // strip thec checksum from display
-        let desc = format!("{}", self.desc);
-        let desc = format!("blinded({},{})", self.blinder, strip_checksum(&desc));
-        let checksum = desc_checksum(&desc).map_err(|_| fmt::Error)?;
-        write!(f, "{}#{}", &desc, &checksum)
+        let desc = format!("blinded({},{:#})", self.blinder, self.desc);
+        write_with_checksum(f, &desc)
     }
 }
 
@@ -193,6 +190,13 @@ where
         self.desc.explicit_script()
     }
 
+    fn redeem_script(&self) -> Option<Script>
+    where
+        Pk: ToPublicKey,
+    {
+        self.desc.redeem_script()
+    }
+
     fn get_satisfaction<S>(&self, satisfier: S) -> Result<(Vec<Vec<u8>>, Script), Error>
     where
         Pk: ToPublicKey,
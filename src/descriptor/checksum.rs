@@ -3,6 +3,7 @@
 //! This module contains a re-implementation of the function used by Bitcoin Core to calculate the
 //! checksum of a descriptor
 
+use std::fmt;
 use std::iter::FromIterator;
 
 use Error;
@@ -78,16 +79,29 @@ pub fn desc_checksum(desc: &str) -> Result<String, Error> {
 /// Helper function for FromStr for various
 /// descriptor types. Checks and verifies the checksum
 /// if it is present and returns the descriptor string
-/// without the checksum
+/// without the checksum. As in Bitcoin Core, the checksum
+/// covers the descriptor exactly as given, so descriptors
+/// with a checksum cannot contain tabs or newlines.
+/// Characters of the descriptor outside the checksum
+/// character set are reported with their position.
 pub(super) fn verify_checksum(s: &str) -> Result<&str, Error> {
     for ch in s.as_bytes() {
-        if *ch < 20 || *ch > 127 {
+        if *ch < 20 && !(*ch as char).is_ascii_whitespace() {
             return Err(Error::Unprintable(*ch));
         }
     }
-
     let mut parts = s.splitn(2, '#');
     let desc_str = parts.next().unwrap();
+    for (pos, ch) in desc_str.chars().enumerate() {
+        if !ch.is_ascii_whitespace() && !INPUT_CHARSET.contains(ch) {
+            return Err(Error::BadDescriptor(format!(
+                "invalid character '{}' at position {}",
+                ch.escape_default(),
+                pos
+            )));
+        }
+    }
+
     if let Some(checksum_str) = parts.next() {
         let expected_sum = desc_checksum(desc_str)?;
         if checksum_str != expected_sum {
@@ -100,6 +114,17 @@ pub(super) fn verify_checksum(s: &str) -> Result<&str, Error> {
     Ok(desc_str)
 }
 
+/// Helper function for Display for various descriptor types.
+/// Writes the descriptor string followed by its checksum, or only
+/// the descriptor string when the alternate flag (`{:#}`) is set.
+pub(super) fn write_with_checksum(f: &mut fmt::Formatter, desc: &str) -> fmt::Result {
+    if f.alternate() {
+        return f.write_str(desc);
+    }
+    let checksum = desc_checksum(desc).map_err(|_| fmt::Error)?;
+    write!(f, "{}#{}", desc, checksum)
+}
+
 /// Helper function to strip checksum without verifying
 #[allow(dead_code)]
 pub(super) fn strip_checksum(s: &str) -> &str {
@@ -139,6 +164,46 @@ mod test {
         );
     }
 
+    #[test]
+    fn verify_checksum_invalid_character() {
+        // Backticks are part of the character set
+        let desc = "elwpkh(`)";
+        let with_checksum = format!("{}#{}", desc, desc_checksum(desc).unwrap());
+        assert_eq!(verify_checksum(&with_checksum).unwrap(), desc);
+
+        assert_eq!(
+            verify_checksum("elwpkh(a\u{1b}b)").unwrap_err().to_string(),
+            "Invalid descriptor: invalid character '\\u{1b}' at position 8"
+        );
+        assert_eq!(
+            verify_checksum("elwpkh(\u{e9}t\u{e9})#abcdefgh")
+                .unwrap_err()
+                .to_string(),
+            "Invalid descriptor: invalid character '\\u{e9}' at position 7"
+        );
+        match verify_checksum("elwpkh(a\u{7}b)") {
+            Err(Error::Unprintable(7)) => {}
+            res => panic!("unexpected {:?}", res),
+        }
+    }
+
+    #[test]
+    fn verify_checksum_whitespace() {
+        // Spaces are part of the character set, and covered by the checksum
+        let desc = "elwpkh( A )";
+        let checksum = desc_checksum(desc).unwrap();
+        assert_ne!(checksum, desc_checksum("elwpkh(A)").unwrap());
+        assert_eq!(
+            verify_checksum(&format!("{}#{}", desc, checksum)).unwrap(),
+            desc
+        );
+        assert!(verify_checksum(&format!("elwpkh(A)#{}", checksum)).is_err());
+
+        // Newlines are not, so only descriptors without a checksum may use them
+        assert_eq!(verify_checksum("elwpkh(\n A\n)").unwrap(), "elwpkh(\n A\n)");
+        assert!(verify_checksum(&format!("elwpkh(\n A\n)#{}", checksum)).is_err());
+    }
+
     #[test]
     fn test_desc_checksum_invalid_character() {
         let sparkle_heart = vec![240, 159, 146, 150];
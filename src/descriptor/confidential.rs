@@ -0,0 +1,367 @@
+// Miniscript
+// Written in 2021 by rust-miniscript developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Confidential Descriptors
+//!
+//! Implementation of `ct(<blinding key>,<descriptor>)` descriptors.
+//! The blinding key is either a single key or a SLIP-77 master
+//! blinding key from which a key is derived for every scriptPubKey.
+//!
+
+use std::{fmt, str::FromStr};
+
+use elements::confidential::{Asset, AssetBlindingFactor, Value, ValueBlindingFactor};
+use elements::hashes::hex::{FromHex, ToHex};
+use elements::secp256k1_zkp;
+use elements::{self, slip77::MasterBlindingKey, Script, TxOutSecrets};
+
+use expression::{self, FromTree};
+use policy::{semantic, Liftable};
+use {Error, MiniscriptKey, Satisfier, ToPublicKey};
+
+use super::{
+    checksum::{verify_checksum, write_with_checksum},
+    Descriptor, DescriptorTrait, ElementsTrait, TranslatePk,
+};
+
+/// The blinding key of a confidential descriptor
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug)]
+pub enum ConfidentialKey<Pk: MiniscriptKey> {
+    /// A SLIP-77 master blinding key
+    Slip77(secp256k1_zkp::SecretKey),
+    /// A single blinding public key
+    Bare(Pk),
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for ConfidentialKey<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfidentialKey::Slip77(ref sk) => write!(f, "slip77({})", sk[..].to_hex()),
+            ConfidentialKey::Bare(ref pk) => write!(f, "{}", pk),
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey> FromStr for ConfidentialKey<Pk>
+where
+    Pk: FromStr,
+    <Pk as FromStr>::Err: ToString,
+{
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("slip77(") && s.ends_with(')') {
+            let bytes = Vec::<u8>::from_hex(&s["slip77(".len()..s.len() - 1])
+                .map_err(|e| Error::BadDescriptor(format!("slip77 key: {}", e)))?;
+            let sk = secp256k1_zkp::SecretKey::from_slice(&bytes)
+                .map_err(|e| Error::BadDescriptor(format!("slip77 key: {}", e)))?;
+            Ok(ConfidentialKey::Slip77(sk))
+        } else {
+            Pk::from_str(s)
+                .map(ConfidentialKey::Bare)
+                .map_err(|e| Error::Unexpected(e.to_string()))
+        }
+    }
+}
+
+/// A descriptor whose addresses are blinded with the blinding key
+/// `key`. Only the unblinded `descriptor` is committed to in the
+/// scriptPubKey.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq)]
+pub struct ConfidentialDescriptor<Pk: MiniscriptKey> {
+    /// The blinding key
+    pub key: ConfidentialKey<Pk>,
+    /// The underlying explicit descriptor
+    pub descriptor: Descriptor<Pk>,
+}
+
+impl<Pk: MiniscriptKey> ConfidentialDescriptor<Pk> {
+    /// Create a new confidential descriptor from a blinding key and descriptor
+    pub fn new(key: ConfidentialKey<Pk>, descriptor: Descriptor<Pk>) -> Self {
+        Self { key, descriptor }
+    }
+
+    /// get the unblinded descriptor
+    pub fn as_explicit(&self) -> &Descriptor<Pk> {
+        &self.descriptor
+    }
+
+    /// get the unblinded descriptor
+    pub fn into_explicit(self) -> Descriptor<Pk> {
+        self.descriptor
+    }
+}
+
+impl<Pk: MiniscriptKey> ConfidentialDescriptor<Pk>
+where
+    Pk: ToPublicKey + FromStr,
+    Pk::Hash: FromStr,
+    <Pk as FromStr>::Err: ToString,
+    <<Pk as MiniscriptKey>::Hash as FromStr>::Err: ToString,
+{
+    /// The blinding public key for the scriptPubKey of this descriptor
+    pub fn blinding_pubkey<C: secp256k1_zkp::Signing>(
+        &self,
+        secp: &secp256k1_zkp::Secp256k1<C>,
+    ) -> secp256k1_zkp::PublicKey {
+        match self.key {
+            ConfidentialKey::Slip77(ref sk) => {
+                let spk = self.descriptor.script_pubkey();
+                let blinding_sk = MasterBlindingKey(*sk).derive_blinding_key(&spk);
+                secp256k1_zkp::PublicKey::from_secret_key(secp, &blinding_sk)
+            }
+            ConfidentialKey::Bare(ref pk) => pk.to_public_key().key,
+        }
+    }
+
+    /// The confidential address of this descriptor
+    pub fn address<C: secp256k1_zkp::Signing>(
+        &self,
+        secp: &secp256k1_zkp::Secp256k1<C>,
+        params: &'static elements::AddressParams,
+    ) -> Result<elements::Address, Error> {
+        self.descriptor
+            .blind_addr(Some(self.blinding_pubkey(secp)), params)
+    }
+
+    /// Whether `txout` pays to this descriptor, blinded or not
+    pub fn matches_txout(&self, txout: &elements::TxOut) -> bool {
+        txout.script_pubkey == self.descriptor.script_pubkey()
+    }
+
+    /// The asset, value and blinding factors of `txout`, which must pay
+    /// to this descriptor. Those of an output received unblinded are
+    /// returned as is, with zero blinding factors. Unblinding a
+    /// confidential output requires a SLIP-77 blinding key.
+    pub fn unblind<C: secp256k1_zkp::Verification>(
+        &self,
+        secp: &secp256k1_zkp::Secp256k1<C>,
+        txout: &elements::TxOut,
+    ) -> Result<TxOutSecrets, Error> {
+        if !self.matches_txout(txout) {
+            return Err(Error::Unexpected(
+                "output does not pay to the descriptor".to_string(),
+            ));
+        }
+        if let (Asset::Explicit(asset), Value::Explicit(value)) = (txout.asset, txout.value) {
+            return Ok(TxOutSecrets::new(
+                asset,
+                AssetBlindingFactor::zero(),
+                value,
+                ValueBlindingFactor::zero(),
+            ));
+        }
+        let blinding_sk = match self.key {
+            ConfidentialKey::Slip77(ref sk) => {
+                MasterBlindingKey(*sk).derive_blinding_key(&txout.script_pubkey)
+            }
+            ConfidentialKey::Bare(..) => {
+                return Err(Error::Unexpected(
+                    "cannot unblind with a blinding public key".to_string(),
+                ))
+            }
+        };
+        txout
+            .unblind(secp, blinding_sk)
+            .map_err(|e| Error::Unexpected(e.to_string()))
+    }
+
+    /// The amount below which a confidential output to this descriptor
+    /// is dust at `dust_relay_fee` satoshis per 1000 virtual bytes.
+    /// This is higher than for the explicit descriptor as it accounts
+    /// for the commitments, rangeproof and surjection proof.
+    pub fn dust_threshold(&self, dust_relay_fee: u64) -> u64 {
+        super::dust_threshold(&self.descriptor.script_pubkey(), true, dust_relay_fee)
+    }
+
+    /// Whether a confidential output of `amount` to this descriptor is
+    /// dust at `dust_relay_fee` satoshis per 1000 virtual bytes
+    pub fn is_dust(&self, amount: u64, dust_relay_fee: u64) -> bool {
+        amount < self.dust_threshold(dust_relay_fee)
+    }
+}
+
+impl<Pk: MiniscriptKey> Liftable<Pk> for ConfidentialDescriptor<Pk> {
+    fn lift(&self) -> Result<semantic::Policy<Pk>, Error> {
+        self.descriptor.lift()
+    }
+}
+
+impl<Pk: MiniscriptKey> ElementsTrait<Pk> for ConfidentialDescriptor<Pk>
+where
+    Pk: FromStr,
+    Pk::Hash: FromStr,
+    <Pk as FromStr>::Err: ToString,
+    <<Pk as MiniscriptKey>::Hash as FromStr>::Err: ToString,
+{
+    /// Overides the blinding key in descriptor with the one
+    /// provided in the argument.
+    fn blind_addr(
+        &self,
+        blinder: Option<secp256k1_zkp::PublicKey>,
+        params: &'static elements::AddressParams,
+    ) -> Result<elements::Address, Error>
+    where
+        Pk: ToPublicKey,
+    {
+        self.descriptor.blind_addr(blinder, params)
+    }
+}
+
+impl<Pk: MiniscriptKey> DescriptorTrait<Pk> for ConfidentialDescriptor<Pk>
+where
+    Pk: FromStr,
+    Pk::Hash: FromStr,
+    <Pk as FromStr>::Err: ToString,
+    <<Pk as MiniscriptKey>::Hash as FromStr>::Err: ToString,
+{
+    fn sanity_check(&self) -> Result<(), Error> {
+        self.descriptor.sanity_check()
+    }
+
+    /// The confidential address of this descriptor. Like
+    /// [ConfidentialDescriptor::address], but deriving SLIP-77 blinding
+    /// keys with a context of its own.
+    fn address(&self, params: &'static elements::AddressParams) -> Result<elements::Address, Error>
+    where
+        Pk: ToPublicKey,
+    {
+        let secp = secp256k1_zkp::Secp256k1::signing_only();
+        ConfidentialDescriptor::address(self, &secp, params)
+    }
+
+    fn script_pubkey(&self) -> Script
+    where
+        Pk: ToPublicKey,
+    {
+        self.descriptor.script_pubkey()
+    }
+
+    fn unsigned_script_sig(&self) -> Script
+    where
+        Pk: ToPublicKey,
+    {
+        self.descriptor.unsigned_script_sig()
+    }
+
+    fn explicit_script(&self) -> Script
+    where
+        Pk: ToPublicKey,
+    {
+        self.descriptor.explicit_script()
+    }
+
+    fn redeem_script(&self) -> Option<Script>
+    where
+        Pk: ToPublicKey,
+    {
+        self.descriptor.redeem_script()
+    }
+
+    fn get_satisfaction<S>(&self, satisfier: S) -> Result<(Vec<Vec<u8>>, Script), Error>
+    where
+        Pk: ToPublicKey,
+        S: Satisfier<Pk>,
+    {
+        self.descriptor.get_satisfaction(satisfier)
+    }
+
+    fn max_satisfaction_weight(&self) -> Result<usize, Error> {
+        self.descriptor.max_satisfaction_weight()
+    }
+
+    fn script_code(&self) -> Script
+    where
+        Pk: ToPublicKey,
+    {
+        self.descriptor.script_code()
+    }
+}
+
+impl<Pk: MiniscriptKey> fmt::Debug for ConfidentialDescriptor<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ct({:?},{:?})", self.key, self.descriptor)
+    }
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for ConfidentialDescriptor<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let desc = format!("ct({},{:#})", self.key, self.descriptor);
+        write_with_checksum(f, &desc)
+    }
+}
+
+impl<Pk: MiniscriptKey> FromTree for ConfidentialDescriptor<Pk>
+where
+    Pk: FromStr,
+    Pk::Hash: FromStr,
+    <Pk as FromStr>::Err: ToString,
+    <<Pk as MiniscriptKey>::Hash as FromStr>::Err: ToString,
+{
+    fn from_tree(top: &expression::Tree) -> Result<Self, Error> {
+        if top.name == "ct" && top.args.len() == 2 {
+            let key = expression::terminal(&top.args[0], ConfidentialKey::from_str)
+                .map_err(|e| expression::arg_error(top, 0, e))?;
+            let descriptor = Descriptor::<Pk>::from_tree(&top.args[1])
+                .map_err(|e| expression::arg_error(top, 1, e))?;
+            Ok(ConfidentialDescriptor { key, descriptor })
+        } else {
+            Err(Error::Unexpected(format!(
+                "{}({} args) while parsing ct descriptor",
+                top.name,
+                top.args.len(),
+            )))
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey> FromStr for ConfidentialDescriptor<Pk>
+where
+    Pk: FromStr,
+    Pk::Hash: FromStr,
+    <Pk as FromStr>::Err: ToString,
+    <<Pk as MiniscriptKey>::Hash as FromStr>::Err: ToString,
+{
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let desc_str = verify_checksum(s)?;
+        let top = expression::Tree::from_str(desc_str)?;
+        Self::from_tree(&top)
+    }
+}
+
+impl<P: MiniscriptKey, Q: MiniscriptKey> TranslatePk<P, Q> for ConfidentialDescriptor<P> {
+    type Output = ConfidentialDescriptor<Q>;
+
+    fn translate_pk<Fpk, Fpkh, E>(
+        &self,
+        mut translatefpk: Fpk,
+        mut translatefpkh: Fpkh,
+    ) -> Result<Self::Output, E>
+    where
+        Fpk: FnMut(&P) -> Result<Q, E>,
+        Fpkh: FnMut(&P::Hash) -> Result<Q::Hash, E>,
+        Q: MiniscriptKey,
+    {
+        let key = match self.key {
+            ConfidentialKey::Slip77(sk) => ConfidentialKey::Slip77(sk),
+            ConfidentialKey::Bare(ref pk) => ConfidentialKey::Bare(translatefpk(pk)?),
+        };
+        Ok(ConfidentialDescriptor::new(
+            key,
+            self.descriptor
+                .translate_pk(&mut translatefpk, &mut translatefpkh)?,
+        ))
+    }
+}
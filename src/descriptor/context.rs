@@ -0,0 +1,257 @@
+// Miniscript
+// Written in 2021 by rust-miniscript developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Context Descriptors
+//!
+//! Descriptors for a top-level miniscript which are generic over its
+//! script context: bare for [BareCtx], wsh for [Segwitv0] and sh for
+//! [Legacy].
+//!
+
+use std::{fmt, str::FromStr};
+
+use elements::{self, secp256k1_zkp, Script};
+
+use miniscript::context::ScriptContext;
+use policy::{semantic, Liftable};
+use {
+    BareCtx, Error, ForEach, ForEachKey, Legacy, Miniscript, MiniscriptKey, Satisfier, Segwitv0,
+    ToPublicKey,
+};
+
+use super::{Descriptor, DescriptorTrait, ElementsTrait};
+
+/// Script contexts whose miniscripts can be used at the top level of a
+/// descriptor
+pub trait DescriptorContext: ScriptContext {
+    /// Wraps the miniscript in the descriptor of this context, running
+    /// the top level checks of the context
+    fn wrap<Pk: MiniscriptKey>(ms: Miniscript<Pk, Self>) -> Result<Descriptor<Pk>, Error>;
+}
+
+impl DescriptorContext for BareCtx {
+    fn wrap<Pk: MiniscriptKey>(ms: Miniscript<Pk, Self>) -> Result<Descriptor<Pk>, Error> {
+        Descriptor::new_bare(ms)
+    }
+}
+
+impl DescriptorContext for Segwitv0 {
+    fn wrap<Pk: MiniscriptKey>(ms: Miniscript<Pk, Self>) -> Result<Descriptor<Pk>, Error> {
+        Descriptor::new_wsh(ms)
+    }
+}
+
+impl DescriptorContext for Legacy {
+    fn wrap<Pk: MiniscriptKey>(ms: Miniscript<Pk, Self>) -> Result<Descriptor<Pk>, Error> {
+        Descriptor::new_sh(ms)
+    }
+}
+
+/// A top-level miniscript in the script context `Ctx`, used as a
+/// bare, wsh or sh descriptor according to the context
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ContextDescriptor<Pk: MiniscriptKey, Ctx: DescriptorContext> {
+    /// underlying miniscript
+    ms: Miniscript<Pk, Ctx>,
+    /// the descriptor wrapping it
+    desc: Descriptor<Pk>,
+}
+
+impl<Pk: MiniscriptKey, Ctx: DescriptorContext> ContextDescriptor<Pk, Ctx> {
+    /// Create a new descriptor, checking the miniscript can be used at
+    /// the top level of `Ctx`
+    pub fn new(ms: Miniscript<Pk, Ctx>) -> Result<Self, Error> {
+        let desc = Ctx::wrap(ms.clone())?;
+        Ok(Self { ms, desc })
+    }
+
+    /// get the inner
+    pub fn as_inner(&self) -> &Miniscript<Pk, Ctx> {
+        &self.ms
+    }
+
+    /// get the inner
+    pub fn into_inner(self) -> Miniscript<Pk, Ctx> {
+        self.ms
+    }
+
+    /// The equivalent [Descriptor]
+    pub fn as_descriptor(&self) -> &Descriptor<Pk> {
+        &self.desc
+    }
+
+    /// Convert into the equivalent [Descriptor]
+    pub fn into_descriptor(self) -> Descriptor<Pk> {
+        self.desc
+    }
+}
+
+impl<Pk: MiniscriptKey, Ctx: DescriptorContext> fmt::Debug for ContextDescriptor<Pk, Ctx> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.desc, f)
+    }
+}
+
+impl<Pk: MiniscriptKey, Ctx: DescriptorContext> fmt::Display for ContextDescriptor<Pk, Ctx> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.desc, f)
+    }
+}
+
+impl<Pk: MiniscriptKey, Ctx: DescriptorContext> Liftable<Pk> for ContextDescriptor<Pk, Ctx> {
+    fn lift(&self) -> Result<semantic::Policy<Pk>, Error> {
+        self.ms.lift()
+    }
+}
+
+impl<Pk: MiniscriptKey, Ctx: DescriptorContext> ForEachKey<Pk> for ContextDescriptor<Pk, Ctx> {
+    fn for_each_key<'a, F: FnMut(ForEach<'a, Pk>) -> bool>(&'a self, pred: F) -> bool
+    where
+        Pk: 'a,
+        Pk::Hash: 'a,
+    {
+        self.ms.for_each_key(pred)
+    }
+}
+
+impl<Pk, Ctx> ElementsTrait<Pk> for ContextDescriptor<Pk, Ctx>
+where
+    Pk: MiniscriptKey + FromStr,
+    Pk::Hash: FromStr,
+    <Pk as FromStr>::Err: ToString,
+    <<Pk as MiniscriptKey>::Hash as FromStr>::Err: ToString,
+    Ctx: DescriptorContext,
+{
+    fn blind_addr(
+        &self,
+        blinder: Option<secp256k1_zkp::PublicKey>,
+        params: &'static elements::AddressParams,
+    ) -> Result<elements::Address, Error>
+    where
+        Pk: ToPublicKey,
+    {
+        self.desc.blind_addr(blinder, params)
+    }
+}
+
+impl<Pk, Ctx> DescriptorTrait<Pk> for ContextDescriptor<Pk, Ctx>
+where
+    Pk: MiniscriptKey + FromStr,
+    Pk::Hash: FromStr,
+    <Pk as FromStr>::Err: ToString,
+    <<Pk as MiniscriptKey>::Hash as FromStr>::Err: ToString,
+    Ctx: DescriptorContext,
+{
+    fn sanity_check(&self) -> Result<(), Error> {
+        self.desc.sanity_check()
+    }
+
+    fn address(&self, params: &'static elements::AddressParams) -> Result<elements::Address, Error>
+    where
+        Pk: ToPublicKey,
+    {
+        self.desc.address(params)
+    }
+
+    fn script_pubkey(&self) -> Script
+    where
+        Pk: ToPublicKey,
+    {
+        self.desc.script_pubkey()
+    }
+
+    fn unsigned_script_sig(&self) -> Script
+    where
+        Pk: ToPublicKey,
+    {
+        self.desc.unsigned_script_sig()
+    }
+
+    fn explicit_script(&self) -> Script
+    where
+        Pk: ToPublicKey,
+    {
+        self.desc.explicit_script()
+    }
+
+    fn redeem_script(&self) -> Option<Script>
+    where
+        Pk: ToPublicKey,
+    {
+        self.desc.redeem_script()
+    }
+
+    fn get_satisfaction<S>(&self, satisfier: S) -> Result<(Vec<Vec<u8>>, Script), Error>
+    where
+        Pk: ToPublicKey,
+        S: Satisfier<Pk>,
+    {
+        self.desc.get_satisfaction(satisfier)
+    }
+
+    fn max_satisfaction_weight(&self) -> Result<usize, Error> {
+        self.desc.max_satisfaction_weight()
+    }
+
+    fn script_code(&self) -> Script
+    where
+        Pk: ToPublicKey,
+    {
+        self.desc.script_code()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::PublicKey;
+    use Terminal;
+
+    const MS: &str = "c:pk_k(020000000000000000000000000000000000000000000000000000000000000002)";
+
+    #[test]
+    fn bare_and_segwitv0() {
+        let bare_ms = Miniscript::<PublicKey, BareCtx>::from_str(MS).unwrap();
+        let wsh_ms = Miniscript::<PublicKey, Segwitv0>::from_str(MS).unwrap();
+        assert_eq!(bare_ms.encode(), wsh_ms.encode());
+
+        let bare = ContextDescriptor::new(bare_ms.clone()).unwrap();
+        assert_eq!(
+            bare.as_descriptor(),
+            &Descriptor::new_bare(bare_ms).unwrap()
+        );
+        assert_eq!(bare.script_pubkey(), bare.as_inner().encode());
+        assert_eq!(bare.explicit_script(), bare.as_inner().encode());
+
+        let wsh = ContextDescriptor::new(wsh_ms.clone()).unwrap();
+        assert_eq!(wsh.as_descriptor(), &Descriptor::new_wsh(wsh_ms).unwrap());
+        assert_eq!(wsh.explicit_script(), wsh.as_inner().encode());
+        assert_eq!(
+            wsh.script_pubkey(),
+            Script::new_v0_wsh(&wsh.as_inner().encode().wscript_hash())
+        );
+        assert!(wsh.to_string().starts_with("elwsh(pk("));
+        assert_eq!(bare.lift().unwrap(), wsh.lift().unwrap());
+    }
+
+    #[test]
+    fn top_level_checks() {
+        // Not of type B, so it cannot be used at the top level
+        let pk = PublicKey::from_str(
+            "020000000000000000000000000000000000000000000000000000000000000002",
+        )
+        .unwrap();
+        let ms = Miniscript::<PublicKey, Segwitv0>::from_ast(Terminal::PkK(pk)).unwrap();
+        assert!(ContextDescriptor::new(ms).is_err());
+    }
+}
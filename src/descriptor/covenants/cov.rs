@@ -66,10 +66,12 @@ use {
 };
 
 use super::super::{
-    checksum::{desc_checksum, verify_checksum},
+    checksum::{verify_checksum, write_with_checksum},
+    segwitv0::check_compressed_keys,
     ElementsTrait, ELMTS_STR,
 };
 use super::{CovError, CovOperations};
+use policy::{semantic, Liftable};
 use {DescriptorTrait, Error, MiniscriptKey, Satisfier, ToPublicKey};
 
 // A simple utility function to serialize an array
@@ -313,8 +315,7 @@ impl<Pk: MiniscriptKey> fmt::Debug for CovenantDescriptor<Pk> {
 impl<Pk: MiniscriptKey> fmt::Display for CovenantDescriptor<Pk> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let desc = format!("{}covwsh({},{})", ELMTS_STR, self.pk, self.ms);
-        let checksum = desc_checksum(&desc).map_err(|_| fmt::Error)?;
-        write!(f, "{}#{}", &desc, &checksum)
+        write_with_checksum(f, &desc)
     }
 }
 
@@ -366,6 +367,7 @@ where
 {
     fn sanity_check(&self) -> Result<(), Error> {
         self.ms.sanity_check()?;
+        check_compressed_keys(self)?;
         // Additional local check for p2wsh script size
         let ss = COV_SCRIPT_SIZE - if self.ms.ext.has_free_verify { 1 } else { 0 };
         if self.ms.script_size() + ss > MAX_STANDARD_P2WSH_SCRIPT_SIZE {
@@ -443,6 +445,21 @@ where
     }
 }
 
+impl<Pk: MiniscriptKey> Liftable<Pk> for CovenantDescriptor<Pk> {
+    fn lift(&self) -> Result<semantic::Policy<Pk>, Error> {
+        // The covenant requires a signature by `pk` over the transaction
+        // data in addition to satisfying the inner miniscript
+        Ok(semantic::Policy::Threshold(
+            2,
+            vec![
+                semantic::Policy::KeyHash(self.pk.to_pubkeyhash()),
+                self.ms.lift()?,
+            ],
+        )
+        .normalized())
+    }
+}
+
 impl<Pk: MiniscriptKey> ForEachKey<Pk> for CovenantDescriptor<Pk> {
     fn for_each_key<'a, F: FnMut(ForEach<'a, Pk>) -> bool>(&'a self, mut pred: F) -> bool
     where
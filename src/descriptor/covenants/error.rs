@@ -31,11 +31,6 @@ pub enum CovError {
     MissingCovSignature,
     /// Bad(Malformed) Covenant Descriptor
     BadCovDescriptor,
-    /// Cannot lift a Covenant Descriptor
-    /// This is because the different components of the covenants
-    /// might interact across branches and thus is
-    /// not composable and could not be analyzed individually.
-    CovenantLift,
     /// The Covenant Sighash type and the satisfier sighash
     /// type must be the same
     CovenantSighashTypeMismatch,
@@ -47,7 +42,6 @@ impl fmt::Display for CovError {
             CovError::MissingScriptCode => write!(f, "Missing Script code"),
             CovError::MissingValue => write!(f, "Missing value"),
             CovError::BadCovDescriptor => write!(f, "Bad or Malformed covenant descriptor"),
-            CovError::CovenantLift => write!(f, "Cannot lift a covenant descriptor"),
             CovError::MissingSighashItem(i) => {
                 write!(f, "Missing sighash item # : {} in satisfier", i)
             }
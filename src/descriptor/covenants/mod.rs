@@ -69,10 +69,12 @@ mod tests {
         TxOut, Txid,
     };
     use interpreter::SatisfiedConstraint;
+    use policy::{self, Liftable};
     use std::str::FromStr;
     use util::{count_non_push_opcodes, witness_size};
     use Interpreter;
     use {descriptor::DescriptorType, Descriptor, ElementsSig, Error, Satisfier};
+    use {Miniscript, Segwitv0};
 
     const BTC_ASSET: [u8; 32] = [
         0x23, 0x0f, 0x4f, 0x5d, 0x4b, 0x7c, 0x6f, 0xa8, 0x45, 0x80, 0x6e, 0xe4, 0xf6, 0x77, 0x13,
@@ -82,7 +84,7 @@ mod tests {
 
     fn string_rtt(desc_str: &str) {
         let desc = Descriptor::<String>::from_str(desc_str).unwrap();
-        assert_eq!(desc.to_string_no_chksum(), desc_str);
+        assert_eq!(desc.to_string_no_checksum(), desc_str);
         let cov_desc = desc.as_cov().unwrap();
         assert_eq!(cov_desc.to_string(), desc.to_string());
     }
@@ -96,6 +98,27 @@ mod tests {
         string_rtt("elcovwsh(A,outputs_pref(01020304))");
     }
 
+    #[test]
+    fn lift_cov() {
+        let desc = Descriptor::<String>::from_str(
+            "elcovwsh(A,and_v(v:outputs_pref(01020304),thresh(2,ver_eq(1),s:pk(B),s:pk(C))))",
+        )
+        .unwrap();
+        let policy = desc.lift().unwrap();
+        assert_eq!(
+            policy.to_string(),
+            "and(pkh(A),outputs_pref(01020304),thresh(2,ver_eq(1),pkh(B),pkh(C)))"
+        );
+        assert_eq!(policy.n_keys(), 3);
+        assert_eq!(policy.minimum_n_keys(), 2);
+        assert!(policy.relative_timelocks().is_empty());
+        // The lifted policy can be parsed back
+        assert_eq!(
+            policy::semantic::Policy::<String>::from_str(&policy.to_string()).unwrap(),
+            policy
+        );
+    }
+
     fn script_rtt(desc_str: &str) {
         let desc = Descriptor::<bitcoin::PublicKey>::from_str(desc_str).unwrap();
         assert_eq!(desc.desc_type(), DescriptorType::Cov);
@@ -466,6 +489,61 @@ mod tests {
         // println!("{}", serialize_hex(&desc.explicit_script()));
     }
 
+    #[test]
+    fn satisfy_introspection_from_tx() {
+        struct TxSat<'a>(&'a Transaction);
+
+        impl<'a> Satisfier<bitcoin::PublicKey> for TxSat<'a> {
+            fn lookup_tx(&self) -> Option<&Transaction> {
+                Some(self.0)
+            }
+
+            fn lookup_input_index(&self) -> Option<usize> {
+                Some(0)
+            }
+        }
+
+        let mut tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![txin_from_txid_vout(
+                "7c8e615c8da947fefd2d9b6f83f313a9b59d249c93a5f232287633195b461cb7",
+                0,
+            )],
+            output: vec![TxOut::default()],
+        };
+        tx.output[0].asset =
+            confidential::Asset::Explicit(AssetId::from_slice(&BTC_ASSET).unwrap());
+        tx.output[0].value = confidential::Value::Explicit(99_000);
+        // Commit to the asset and value of the first output
+        let pref = serialize(&tx.output[0])[..42].to_vec();
+        let ms = Miniscript::<bitcoin::PublicKey, Segwitv0>::from_str_insane(&format!(
+            "outputs_pref({})",
+            pref.to_hex()
+        ))
+        .unwrap();
+
+        let sat = TxSat(&tx);
+        assert_eq!(Satisfier::lookup_nversion(&sat), Some(2));
+        assert_eq!(
+            Satisfier::lookup_outpoint(&sat),
+            Some(tx.input[0].previous_output)
+        );
+        assert!(ms.satisfy(sat).is_ok());
+        // Without the transaction the fragment cannot be satisfied
+        match ms.satisfy(()) {
+            Err(Error::CouldNotSatisfy) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let mut other = tx.clone();
+        other.output[0].value = confidential::Value::Explicit(98_000);
+        match ms.satisfy(TxSat(&other)) {
+            Err(Error::CouldNotSatisfy) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
     fn txin_from_txid_vout(txid: &str, vout: u32) -> TxIn {
         TxIn {
             previous_output: OutPoint {
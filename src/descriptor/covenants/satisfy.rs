@@ -110,6 +110,14 @@ impl<'tx, 'ptx> CovSatisfier<'tx, 'ptx> {
 }
 
 impl<'tx, 'ptx, Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for CovSatisfier<'tx, 'ptx> {
+    fn lookup_tx(&self) -> Option<&elements::Transaction> {
+        Some(self.tx)
+    }
+
+    fn lookup_input_index(&self) -> Option<usize> {
+        Some(self.idx as usize)
+    }
+
     fn lookup_nversion(&self) -> Option<u32> {
         Some(self.tx.version)
     }
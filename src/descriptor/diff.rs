@@ -0,0 +1,198 @@
+// Miniscript
+// Written in 2021 by rust-miniscript developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Descriptor Diffs
+//!
+//! Reports the differences between two descriptors, split into the
+//! keys that were added, removed or moved and whether anything else changed.
+//!
+
+use std::fmt;
+
+use {DummyKey, DummyKeyHash, ForEach, ForEachKey, MiniscriptKey, TranslatePk};
+
+use super::Descriptor;
+
+/// The difference between an old and a new descriptor
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DescriptorDiff<Pk: MiniscriptKey> {
+    /// Keys present only in the new descriptor
+    pub added_keys: Vec<Pk>,
+    /// Keys present only in the old descriptor
+    pub removed_keys: Vec<Pk>,
+    /// Key hashes present only in the new descriptor
+    pub added_key_hashes: Vec<Pk::Hash>,
+    /// Key hashes present only in the old descriptor
+    pub removed_key_hashes: Vec<Pk::Hash>,
+    /// Keys present in both descriptors, but now at a position that held
+    /// a different key. Only reported when the structure is unchanged.
+    pub moved_keys: Vec<Pk>,
+    /// Key hashes present in both descriptors, but now at a position that
+    /// held a different hash. Only reported when the structure is unchanged.
+    pub moved_key_hashes: Vec<Pk::Hash>,
+    /// Whether the descriptors differ in anything other than their keys,
+    /// such as the miniscript fragments, thresholds or timelocks
+    pub structure_changed: bool,
+}
+
+impl<Pk: MiniscriptKey> DescriptorDiff<Pk> {
+    /// Whether the two descriptors are identical
+    pub fn is_empty(&self) -> bool {
+        self.added_keys.is_empty()
+            && self.removed_keys.is_empty()
+            && self.added_key_hashes.is_empty()
+            && self.removed_key_hashes.is_empty()
+            && self.moved_keys.is_empty()
+            && self.moved_key_hashes.is_empty()
+            && !self.structure_changed
+    }
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for DescriptorDiff<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("no changes");
+        }
+        let mut changes = vec![];
+        changes.extend(self.added_keys.iter().map(|pk| format!("+{}", pk)));
+        changes.extend(self.removed_keys.iter().map(|pk| format!("-{}", pk)));
+        changes.extend(self.added_key_hashes.iter().map(|h| format!("+{}", h)));
+        changes.extend(self.removed_key_hashes.iter().map(|h| format!("-{}", h)));
+        changes.extend(self.moved_keys.iter().map(|pk| format!("~{}", pk)));
+        changes.extend(self.moved_key_hashes.iter().map(|h| format!("~{}", h)));
+        if self.structure_changed {
+            changes.push("structure changed".to_owned());
+        }
+        f.write_str(&changes.join(", "))
+    }
+}
+
+/// Returns the elements only in `new` and the elements only in `old`
+pub(crate) fn added_and_removed<T: Clone + PartialEq>(old: &[T], new: &[T]) -> (Vec<T>, Vec<T>) {
+    let added = new.iter().filter(|x| !old.contains(x)).cloned().collect();
+    let removed = old.iter().filter(|x| !new.contains(x)).cloned().collect();
+    (added, removed)
+}
+
+/// Returns the elements of `new` that are also in `old`, but sit at a
+/// position where `old` has a different element
+pub(crate) fn moved<T: Clone + PartialEq>(old: &[T], new: &[T]) -> Vec<T> {
+    new.iter()
+        .zip(old)
+        .filter(|&(n, o)| n != o && old.contains(n))
+        .map(|(n, _)| n.clone())
+        .collect()
+}
+
+// Splits the keys and the key hashes of a descriptor
+fn keys<Pk: MiniscriptKey>(desc: &Descriptor<Pk>) -> (Vec<Pk>, Vec<Pk::Hash>) {
+    let mut pks = vec![];
+    let mut hashes = vec![];
+    desc.for_each_key(|key| {
+        match key {
+            ForEach::Key(pk) => pks.push(pk.clone()),
+            ForEach::Hash(hash) => hashes.push(hash.clone()),
+        }
+        true
+    });
+    (pks, hashes)
+}
+
+impl<Pk: MiniscriptKey> Descriptor<Pk> {
+    /// Reports the keys added, removed and moved in `other` compared to
+    /// `self`, and whether the descriptors differ in anything else.
+    ///
+    /// Keys are compared by position, so swapping two keys is reported
+    /// as both of them moving.
+    pub fn diff(&self, other: &Self) -> DescriptorDiff<Pk> {
+        let (old_pks, old_hashes) = keys(self);
+        let (new_pks, new_hashes) = keys(other);
+        let (added_keys, removed_keys) = added_and_removed(&old_pks, &new_pks);
+        let (added_key_hashes, removed_key_hashes) = added_and_removed(&old_hashes, &new_hashes);
+
+        // Erase all keys so that only the shape is compared
+        let shape = |desc: &Self| -> Descriptor<DummyKey> {
+            desc.translate_pk_infallible(|_| DummyKey, |_| DummyKeyHash)
+        };
+        let structure_changed = shape(self) != shape(other);
+        // Positions only line up when both descriptors have the same shape
+        let (moved_keys, moved_key_hashes) = if structure_changed {
+            (vec![], vec![])
+        } else {
+            (moved(&old_pks, &new_pks), moved(&old_hashes, &new_hashes))
+        };
+        DescriptorDiff {
+            added_keys,
+            removed_keys,
+            added_key_hashes,
+            removed_key_hashes,
+            moved_keys,
+            moved_key_hashes,
+            structure_changed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::PublicKey;
+    use std::str::FromStr;
+
+    const PK_A: &str = "020000000000000000000000000000000000000000000000000000000000000002";
+    const PK_B: &str = "020000000000000000000000000000000000000000000000000000000000000003";
+    const PK_C: &str = "020000000000000000000000000000000000000000000000000000000000000004";
+
+    fn desc(s: &str) -> Descriptor<PublicKey> {
+        let s = s.replace("A", PK_A).replace("B", PK_B).replace("C", PK_C);
+        Descriptor::from_str(&s).unwrap()
+    }
+
+    #[test]
+    fn diff_keys_and_structure() {
+        let old = desc("elwsh(multi(2,A,B))");
+        assert!(old.diff(&old).is_empty());
+        assert_eq!(old.diff(&old).to_string(), "no changes");
+
+        let rotated = old.diff(&desc("elwsh(multi(2,A,C))"));
+        assert_eq!(rotated.added_keys, vec![PublicKey::from_str(PK_C).unwrap()]);
+        assert_eq!(
+            rotated.removed_keys,
+            vec![PublicKey::from_str(PK_B).unwrap()]
+        );
+        assert!(!rotated.structure_changed);
+        assert_eq!(rotated.to_string(), format!("+{}, -{}", PK_C, PK_B));
+
+        let threshold = old.diff(&desc("elwsh(multi(1,A,B))"));
+        assert!(threshold.added_keys.is_empty() && threshold.removed_keys.is_empty());
+        assert!(threshold.structure_changed);
+
+        let wrapped = old.diff(&desc("elsh(wsh(multi(2,A,B)))"));
+        assert!(wrapped.structure_changed);
+    }
+
+    #[test]
+    fn diff_swapped_keys() {
+        let old = desc("elwsh(or_d(pk(A),and_v(v:pk(B),older(144))))");
+        let swapped = old.diff(&desc("elwsh(or_d(pk(B),and_v(v:pk(A),older(144))))"));
+        assert!(!swapped.is_empty());
+        assert!(swapped.added_keys.is_empty() && swapped.removed_keys.is_empty());
+        assert!(!swapped.structure_changed);
+        let (a, b) = (
+            PublicKey::from_str(PK_A).unwrap(),
+            PublicKey::from_str(PK_B).unwrap(),
+        );
+        assert_eq!(swapped.moved_keys, vec![b, a]);
+        assert_eq!(swapped.to_string(), format!("~{}, ~{}", PK_B, PK_A));
+    }
+}
@@ -0,0 +1,142 @@
+// Miniscript
+// Written in 2021 by rust-miniscript developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Descriptor Inference
+//!
+//! Recovers the type of descriptor behind a standard scriptPubKey.
+//! Only hashes are committed to in such scripts, so the result is
+//! a skeleton that records the hash in place of the key or script.
+//!
+
+use std::fmt;
+
+use elements::hashes::Hash;
+use elements::{PubkeyHash, Script, ScriptHash, WPubkeyHash, WScriptHash};
+
+/// A descriptor skeleton inferred from a standard scriptPubKey
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum InferredDescriptor {
+    /// A p2pkh output paying to the key with this hash
+    Pkh(PubkeyHash),
+    /// A p2wpkh output paying to the key with this hash
+    Wpkh(WPubkeyHash),
+    /// A p2sh output paying to the script with this hash
+    Sh(ScriptHash),
+    /// A p2wsh output paying to the script with this hash
+    Wsh(WScriptHash),
+}
+
+impl InferredDescriptor {
+    /// The scriptPubKey this skeleton was inferred from
+    pub fn script_pubkey(&self) -> Script {
+        match *self {
+            InferredDescriptor::Pkh(ref hash) => Script::new_p2pkh(hash),
+            InferredDescriptor::Wpkh(ref hash) => Script::new_v0_wpkh(hash),
+            InferredDescriptor::Sh(ref hash) => Script::new_p2sh(hash),
+            InferredDescriptor::Wsh(ref hash) => Script::new_v0_wsh(hash),
+        }
+    }
+}
+
+/// Displays the skeleton as `<type>-hash:<hex>`, e.g. `pkh-hash:<hex>`.
+/// This is deliberately not descriptor syntax, since the key or script
+/// behind the hash is unknown.
+impl fmt::Display for InferredDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InferredDescriptor::Pkh(ref hash) => write!(f, "pkh-hash:{}", hash),
+            InferredDescriptor::Wpkh(ref hash) => write!(f, "wpkh-hash:{}", hash),
+            InferredDescriptor::Sh(ref hash) => write!(f, "sh-hash:{}", hash),
+            InferredDescriptor::Wsh(ref hash) => write!(f, "wsh-hash:{}", hash),
+        }
+    }
+}
+
+/// Infers the descriptor behind a p2pkh, p2wpkh, p2sh or p2wsh
+/// scriptPubKey. Returns `None` for any other script.
+pub fn descriptor_from_script(spk: &Script) -> Option<InferredDescriptor> {
+    let bytes = spk.as_bytes();
+    if spk.is_p2pkh() {
+        PubkeyHash::from_slice(&bytes[3..23])
+            .ok()
+            .map(InferredDescriptor::Pkh)
+    } else if spk.is_v0_p2wpkh() {
+        WPubkeyHash::from_slice(&bytes[2..22])
+            .ok()
+            .map(InferredDescriptor::Wpkh)
+    } else if spk.is_p2sh() {
+        ScriptHash::from_slice(&bytes[2..22])
+            .ok()
+            .map(InferredDescriptor::Sh)
+    } else if spk.is_v0_p2wsh() {
+        WScriptHash::from_slice(&bytes[2..34])
+            .ok()
+            .map(InferredDescriptor::Wsh)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::PublicKey;
+    use std::str::FromStr;
+    use {Descriptor, DescriptorTrait};
+
+    fn infer(desc: &str) -> InferredDescriptor {
+        let desc = Descriptor::<PublicKey>::from_str(desc).unwrap();
+        let inferred = descriptor_from_script(&desc.script_pubkey()).unwrap();
+        assert_eq!(inferred.script_pubkey(), desc.script_pubkey());
+        inferred
+    }
+
+    #[test]
+    fn infer_standard_templates() {
+        let pk = "020000000000000000000000000000000000000000000000000000000000000002";
+        let pkh = PublicKey::from_str(pk).unwrap().pubkey_hash();
+
+        let inferred = infer(&format!("elpkh({})", pk));
+        assert_eq!(
+            inferred,
+            InferredDescriptor::Pkh(PubkeyHash::from_inner(pkh.into_inner()))
+        );
+        assert_eq!(inferred.to_string(), format!("pkh-hash:{}", pkh));
+        assert!(Descriptor::<PublicKey>::from_str(&inferred.to_string()).is_err());
+
+        let inferred = infer(&format!("elwpkh({})", pk));
+        assert_eq!(
+            inferred,
+            InferredDescriptor::Wpkh(WPubkeyHash::from_inner(pkh.into_inner()))
+        );
+        assert_eq!(inferred.to_string(), format!("wpkh-hash:{}", pkh));
+        assert!(Descriptor::<PublicKey>::from_str(&inferred.to_string()).is_err());
+
+        match infer(&format!("elsh(wpkh({}))", pk)) {
+            InferredDescriptor::Sh(..) => {}
+            inferred => panic!("unexpected {}", inferred),
+        }
+        match infer(&format!("elwsh(pk({}))", pk)) {
+            InferredDescriptor::Wsh(..) => {}
+            inferred => panic!("unexpected {}", inferred),
+        }
+    }
+
+    #[test]
+    fn infer_non_standard() {
+        let pk = "020000000000000000000000000000000000000000000000000000000000000002";
+        let bare = Descriptor::<PublicKey>::from_str(&format!("elpk({})", pk)).unwrap();
+        assert_eq!(descriptor_from_script(&bare.script_pubkey()), None);
+        assert_eq!(descriptor_from_script(&Script::new()), None);
+    }
+}
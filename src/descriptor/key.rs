@@ -1,4 +1,4 @@
-use std::{error, fmt, str::FromStr};
+use std::{cell::RefCell, collections::HashMap, error, fmt, str::FromStr};
 
 use bitcoin::{self, util::bip32, XpubIdentifier};
 
@@ -454,6 +454,75 @@ impl DescriptorPublicKey {
     }
 }
 
+/// A cache of the extended keys of ranged descriptor keys, derived up
+/// to their wildcard. Deriving a key at many indices through
+/// [DescriptorPublicKey::derive_public_key_cached] then only performs
+/// the last derivation step for every index.
+#[derive(Debug, Default)]
+pub struct DerivationCache {
+    xpubs: RefCell<HashMap<(bip32::ExtendedPubKey, bip32::DerivationPath), bip32::ExtendedPubKey>>,
+}
+
+impl DerivationCache {
+    /// Create an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of extended keys in the cache
+    pub fn len(&self) -> usize {
+        self.xpubs.borrow().len()
+    }
+
+    /// Whether the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.xpubs.borrow().is_empty()
+    }
+}
+
+impl DescriptorPublicKey {
+    /// Computes the public key of this descriptor key at `index`, as
+    /// `self.clone().derive(index).derive_public_key(secp)` does. For keys
+    /// with an unhardened wildcard, the extended key derived up to the
+    /// wildcard is looked up in, or added to, `cache`.
+    ///
+    /// Panics if given an index ≥ 2^31
+    pub fn derive_public_key_cached<C: secp256k1_zkp::Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        index: u32,
+        cache: &DerivationCache,
+    ) -> Result<bitcoin::PublicKey, ConversionError> {
+        let xpk = match *self {
+            DescriptorPublicKey::XPub(ref xpk) if xpk.wildcard == Wildcard::Unhardened => xpk,
+            _ => return self.clone().derive(index).derive_public_key(secp),
+        };
+        let map_err = |e| match e {
+            bip32::Error::CannotDeriveFromHardenedKey => ConversionError::HardenedChild,
+            e => unreachable!("cryptographically unreachable: {}", e),
+        };
+
+        let cache_key = (xpk.xkey, xpk.derivation_path.clone());
+        let cached = cache.xpubs.borrow().get(&cache_key).cloned();
+        let parent = match cached {
+            Some(parent) => parent,
+            None => {
+                let parent = xpk
+                    .xkey
+                    .derive_pub(secp, &xpk.derivation_path.as_ref())
+                    .map_err(map_err)?;
+                cache.xpubs.borrow_mut().insert(cache_key, parent);
+                parent
+            }
+        };
+        let child = bip32::ChildNumber::from_normal_idx(index).unwrap();
+        parent
+            .ckd_pub(secp, child)
+            .map(|xpub| xpub.public_key)
+            .map_err(map_err)
+    }
+}
+
 impl FromStr for DescriptorSecretKey {
     type Err = DescriptorKeyParseError;
 
@@ -668,7 +737,12 @@ impl MiniscriptKey for DescriptorPublicKey {
 
 #[cfg(test)]
 mod test {
-    use super::{DescriptorKeyParseError, DescriptorPublicKey, DescriptorSecretKey};
+    use super::{
+        DerivationCache, DescriptorKeyParseError, DescriptorPublicKey, DescriptorSecretKey,
+    };
+    use bitcoin::util::bip32;
+    use Descriptor;
+    use TranslatePk2;
 
     use elements::secp256k1_zkp;
 
@@ -822,4 +896,44 @@ mod test {
             "m/90'/0'/1'/2"
         );
     }
+
+    #[test]
+    fn derivation_cache() {
+        let secp = secp256k1_zkp::Secp256k1::verification_only();
+        let desc = Descriptor::<DescriptorPublicKey>::from_str(
+            "elwsh(multi(1,xpub661MyMwAqRbcFW31YEwpkMuc5THy2PSt5bDMsktWQcFF8syAmRUapSCGu8ED9W6oDMSgv6Zz8idoc4a6mr8BDzTJY47LJhkJ8UB7WEGuduB/1/0/*,xpub69H7F5d8KSRgmmdJg2KhpAK8SR3DjMwAdkxj3ZuxV27CprR9LgpeyGmXUbC6wb7ERfvrnKZjXoUmmDznezpbZb7ap6r1D3tgFxHmwMkQTPH/0/1,020000000000000000000000000000000000000000000000000000000000000002))",
+        )
+        .unwrap();
+
+        let cache = DerivationCache::new();
+        for index in 0..1000 {
+            let uncached = desc
+                .derive(index)
+                .translate_pk2(|xpk| xpk.derive_public_key(&secp))
+                .unwrap();
+            let cached = desc
+                .at_derivation_index_cached(&secp, index, &cache)
+                .unwrap();
+            assert_eq!(cached, uncached);
+        }
+        // Only the ranged key is derived up to its wildcard, once
+        assert_eq!(cache.len(), 1);
+
+        // Later derivations start from the cached key
+        let xpub = DescriptorPublicKey::from_str("xpub661MyMwAqRbcFW31YEwpkMuc5THy2PSt5bDMsktWQcFF8syAmRUapSCGu8ED9W6oDMSgv6Zz8idoc4a6mr8BDzTJY47LJhkJ8UB7WEGuduB/1/0/*").unwrap();
+        let fresh = xpub
+            .derive_public_key_cached(&secp, 5, &DerivationCache::new())
+            .unwrap();
+        assert_eq!(
+            xpub.derive_public_key_cached(&secp, 5, &cache).unwrap(),
+            fresh
+        );
+        for parent in cache.xpubs.borrow_mut().values_mut() {
+            *parent = parent.ckd_pub(&secp, bip32::ChildNumber::from(0)).unwrap();
+        }
+        assert_ne!(
+            xpub.derive_public_key_cached(&secp, 5, &cache).unwrap(),
+            fresh
+        );
+    }
 }
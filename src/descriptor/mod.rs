@@ -23,7 +23,7 @@
 //! these with BIP32 paths, pay-to-contract instructions, etc.
 //!
 
-use std::{collections::HashMap, sync::Arc};
+use std::{cell::RefCell, cmp, collections::HashMap, ops::Range, sync::Arc};
 use std::{
     fmt,
     str::{self, FromStr},
@@ -34,27 +34,40 @@ pub mod pegin;
 
 // use bitcoin;
 use elements;
+use elements::opcodes;
+use elements::script::Instruction;
 use elements::secp256k1_zkp;
 use elements::Script;
 
 use self::checksum::verify_checksum;
 use expression;
+use interpreter::{Interpreter, SatisfiedConstraint};
 use miniscript;
+use miniscript::limits::{MAX_OPS_PER_SCRIPT, MAX_SCRIPT_SIZE};
 use miniscript::{Legacy, Miniscript, Segwitv0};
+use util::varint_len;
 use {
-    BareCtx, Error, ForEach, ForEachKey, MiniscriptKey, Satisfier, ToPublicKey, TranslatePk,
-    TranslatePk2,
+    BareCtx, ElementsSig, Error, ForEach, ForEachKey, MiniscriptKey, Satisfier, ToPublicKey,
+    TranslatePk, TranslatePk2,
 };
 
 mod bare;
 mod blinded;
+mod confidential;
+mod context;
 mod covenants;
+mod diff;
+mod infer;
 mod segwitv0;
 mod sh;
 mod sortedmulti;
 // Descriptor Exports
 pub use self::bare::{Bare, Pkh};
 pub use self::blinded::Blinded;
+pub use self::confidential::{ConfidentialDescriptor, ConfidentialKey};
+pub use self::context::{ContextDescriptor, DescriptorContext};
+pub use self::diff::DescriptorDiff;
+pub use self::infer::{descriptor_from_script, InferredDescriptor};
 pub use self::segwitv0::{Wpkh, Wsh, WshInner};
 pub use self::sh::{Sh, ShInner};
 pub use self::sortedmulti::SortedMultiVec;
@@ -62,8 +75,9 @@ mod checksum;
 mod key;
 pub use self::covenants::{CovError, CovOperations, CovSatisfier, CovenantDescriptor};
 pub use self::key::{
-    DescriptorKeyParseError, DescriptorPublicKey, DescriptorSecretKey, DescriptorSinglePriv,
-    DescriptorSinglePub, DescriptorXKey, InnerXKey, Wildcard,
+    ConversionError, DerivationCache, DescriptorKeyParseError, DescriptorPublicKey,
+    DescriptorSecretKey, DescriptorSinglePriv, DescriptorSinglePub, DescriptorXKey, InnerXKey,
+    Wildcard,
 };
 
 /// Alias type for a map of public key to secret key
@@ -76,6 +90,68 @@ pub type KeyMap = HashMap<DescriptorPublicKey, DescriptorSecretKey>;
 
 /// Elements Descriptor String Prefix
 pub const ELMTS_STR: &str = "el";
+
+/// Default fee rate, in satoshis per 1000 virtual bytes, used by Elements
+/// nodes to compute the dust threshold of an output
+pub const DUST_RELAY_TX_FEE: u64 = 3000;
+
+/// Size of the rangeproof Elements attaches to a confidential output
+/// (52-bit range, zero exponent)
+const DEFAULT_RANGEPROOF_SIZE: usize = 4174;
+
+/// Size of the surjection proof of a confidential output spent by a
+/// transaction with a single input
+const SINGLE_INPUT_SURJECTIONPROOF_SIZE: usize = 67;
+
+/// The amount below which an output with scriptPubKey `spk` is dust at
+/// `dust_relay_fee` (satoshis per 1000 virtual bytes). This is the fee for
+/// the output itself plus an input spending it, as in Elements Core. A
+/// confidential output additionally carries value, asset and nonce
+/// commitments and, in its witness, a rangeproof and surjection proof.
+pub(crate) fn dust_threshold(spk: &Script, confidential: bool, dust_relay_fee: u64) -> u64 {
+    if spk.is_provably_unspendable() {
+        return 0;
+    }
+    let script_size = varint_len(spk.len()) + spk.len();
+    let size = if confidential {
+        let wit_size = varint_len(SINGLE_INPUT_SURJECTIONPROOF_SIZE)
+            + SINGLE_INPUT_SURJECTIONPROOF_SIZE
+            + varint_len(DEFAULT_RANGEPROOF_SIZE)
+            + DEFAULT_RANGEPROOF_SIZE;
+        // asset, value and nonce commitments
+        33 + 33 + 33 + script_size + wit_size / 4
+    } else {
+        // explicit asset, explicit value and null nonce
+        33 + 9 + 1 + script_size
+    };
+    // prevout, empty scriptSig and nSequence, with a 107 byte
+    // signature and key in the scriptSig or witness
+    let spend_size = if spk.is_witness_program() {
+        32 + 4 + 1 + 107 / 4 + 4
+    } else {
+        32 + 4 + 1 + 107 + 4
+    };
+    (size + spend_size) as u64 * dust_relay_fee / 1000
+}
+
+/// A satisfying witness and scriptSig for a descriptor, together with
+/// the transaction `nLockTime` and input `nSequence` that are required
+/// for the witness to be valid.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Satisfaction {
+    /// The witness stack
+    pub witness: Vec<Vec<u8>>,
+    /// The scriptSig
+    pub script_sig: Script,
+    /// The minimal `nLockTime` of the spending transaction. This is the
+    /// largest `after` value used by the satisfaction, or 0 if it uses none.
+    pub lock_time: u32,
+    /// The minimal `nSequence` of the spending input. This is the largest
+    /// `older` value used by the satisfaction. If it uses none, this is
+    /// `0xfffffffe` when `lock_time` must be enforced and `0xffffffff`
+    /// otherwise.
+    pub sequence: u32,
+}
 /// Elements specific additional features that
 /// we want on DescriptorTrait from upstream.
 // Maintained as a separate trait to avoid conflicts.
@@ -88,6 +164,23 @@ pub trait ElementsTrait<Pk: MiniscriptKey> {
     ) -> Result<elements::Address, Error>
     where
         Pk: ToPublicKey;
+
+    /// Compute the address of the descriptor. The address is confidential
+    /// if a `blinder` is provided and explicit otherwise.
+    fn address_maybe_blinded(
+        &self,
+        blinder: Option<secp256k1_zkp::PublicKey>,
+        params: &'static elements::AddressParams,
+    ) -> Result<elements::Address, Error>
+    where
+        Pk: ToPublicKey,
+        Self: DescriptorTrait<Pk>,
+    {
+        match blinder {
+            Some(blinder) => self.blind_addr(Some(blinder), params),
+            None => self.address(params),
+        }
+    }
 }
 
 /// A general trait for Bitcoin descriptor.
@@ -138,6 +231,56 @@ pub trait DescriptorTrait<Pk: MiniscriptKey>: ElementsTrait<Pk> {
     where
         Pk: ToPublicKey;
 
+    /// Computes the p2sh redeemScript of the descriptor, i.e. the script
+    /// whose hash is committed to in the scriptPubkey. For `ShWsh` and `ShWpkh`
+    /// this is the segwit program `OP_0 <hash>` pushed by the scriptSig.
+    /// Returns `None` for descriptors which are not wrapped in sh.
+    fn redeem_script(&self) -> Option<Script>
+    where
+        Pk: ToPublicKey,
+    {
+        None
+    }
+
+    /// Checks the scripts of the descriptor against the Elements
+    /// standardness rules: the unsigned scriptSig must be push only, and
+    /// the executed script must be at most [MAX_SCRIPT_SIZE] bytes with at
+    /// most [MAX_OPS_PER_SCRIPT] non-push opcodes.
+    fn check_standardness(&self) -> Result<(), Error>
+    where
+        Pk: ToPublicKey,
+    {
+        let script_sig = self.unsigned_script_sig();
+        if script_sig.instructions().any(|ins| match ins {
+            Ok(Instruction::PushBytes(..)) => false,
+            Ok(Instruction::Op(op)) => op.into_u8() > opcodes::all::OP_PUSHNUM_16.into_u8(),
+            Err(..) => true,
+        }) {
+            return Err(Error::NonPushOnlyScriptSig);
+        }
+
+        let script = self.explicit_script();
+        if script.len() > MAX_SCRIPT_SIZE {
+            return Err(Error::StandardScriptSizeExceeded(script.len()));
+        }
+        // All non-push opcodes count towards the limit,
+        // including those in branches which are not executed
+        let mut ops = 0;
+        for ins in script.instructions() {
+            match ins {
+                Ok(Instruction::Op(op)) if op.into_u8() > opcodes::all::OP_PUSHNUM_16.into_u8() => {
+                    ops += 1
+                }
+                Ok(..) => {}
+                Err(e) => return Err(Error::BadDescriptor(e.to_string())),
+            }
+        }
+        if ops > MAX_OPS_PER_SCRIPT {
+            return Err(Error::StandardOpCountExceeded(ops));
+        }
+        Ok(())
+    }
+
     /// Returns satisfying witness and scriptSig to spend an
     /// output controlled by the given descriptor if it possible to
     /// construct one using the satisfier S.
@@ -146,6 +289,50 @@ pub trait DescriptorTrait<Pk: MiniscriptKey>: ElementsTrait<Pk> {
         Pk: ToPublicKey,
         S: Satisfier<Pk>;
 
+    /// Returns satisfying witness and scriptSig to spend an output
+    /// controlled by the given descriptor, along with the `nLockTime`
+    /// and `nSequence` the spending transaction must set for the
+    /// chosen spending path.
+    fn get_satisfaction_with_timelocks<S>(&self, satisfier: S) -> Result<Satisfaction, Error>
+    where
+        Pk: ToPublicKey,
+        S: Satisfier<Pk>,
+    {
+        let (witness, script_sig) = self.get_satisfaction(satisfier)?;
+        let (lock_time, sequence) = {
+            // Run the produced witness through the interpreter to find
+            // the timelocks used by the chosen spending path
+            let spk = self.script_pubkey();
+            let mut interpreter =
+                Interpreter::from_txdata(&spk, &script_sig, &witness, u32::MAX, u32::MAX)?;
+            let mut lock_time = 0;
+            let mut sequence = None;
+            for constraint in interpreter.iter(|_, _| true) {
+                match constraint? {
+                    SatisfiedConstraint::AbsoluteTimeLock { time } => {
+                        lock_time = cmp::max(lock_time, *time)
+                    }
+                    SatisfiedConstraint::RelativeTimeLock { time } => {
+                        sequence = Some(cmp::max(sequence.unwrap_or(0), *time))
+                    }
+                    _ => {}
+                }
+            }
+            let sequence = match sequence {
+                Some(seq) => seq,
+                None if lock_time > 0 => 0xfffffffe,
+                None => 0xffffffff,
+            };
+            (lock_time, sequence)
+        };
+        Ok(Satisfaction {
+            witness,
+            script_sig,
+            lock_time,
+            sequence,
+        })
+    }
+
     /// Attempts to produce a satisfying witness and scriptSig to spend an
     /// output controlled by the given descriptor; add the data to a given
     /// `TxIn` output.
@@ -168,6 +355,14 @@ pub trait DescriptorTrait<Pk: MiniscriptKey>: ElementsTrait<Pk> {
     /// Returns Error when the descriptor is impossible to safisfy (ex: sh(OP_FALSE))
     fn max_satisfaction_weight(&self) -> Result<usize, Error>;
 
+    /// Computes an upper bound on the virtual size of a satisfying witness
+    /// to the transaction, i.e. [DescriptorTrait::max_satisfaction_weight]
+    /// divided by 4 and rounded up.
+    /// Returns Error when the descriptor is impossible to safisfy (ex: sh(OP_FALSE))
+    fn max_satisfaction_vsize(&self) -> Result<usize, Error> {
+        Ok((self.max_satisfaction_weight()? + 3) / 4)
+    }
+
     /// Get the `scriptCode` of a transaction output.
     ///
     /// The `scriptCode` is the Script of the previous transaction output being serialized in the
@@ -175,6 +370,98 @@ pub trait DescriptorTrait<Pk: MiniscriptKey>: ElementsTrait<Pk> {
     fn script_code(&self) -> Script
     where
         Pk: ToPublicKey;
+
+    /// The keys whose signatures may be used to satisfy the descriptor,
+    /// in the order the satisfier is asked for them, paired with the
+    /// `sighash` to sign with. These are all candidates across all
+    /// spending paths: a `multi(2,A,B,C)` lists all three keys, although
+    /// any two signatures suffice. Useful to request signatures from
+    /// hardware signers before satisfying.
+    ///
+    /// Keys of `pkh` fragments are only known by their hash, and are
+    /// listed by [DescriptorTrait::required_key_hash_signatures] instead.
+    fn required_signatures(
+        &self,
+        sighash: elements::SigHashType,
+    ) -> Vec<(Pk, elements::SigHashType)>
+    where
+        Pk: ToPublicKey,
+    {
+        probe_signatures(self)
+            .keys
+            .into_inner()
+            .into_iter()
+            .map(|pk| (pk, sighash))
+            .collect()
+    }
+
+    /// Like [DescriptorTrait::required_signatures], but for the keys only
+    /// known by their hash, as in the `pkh` fragments of miniscript.
+    fn required_key_hash_signatures(
+        &self,
+        sighash: elements::SigHashType,
+    ) -> Vec<(Pk::Hash, elements::SigHashType)>
+    where
+        Pk: ToPublicKey,
+    {
+        probe_signatures(self)
+            .hashes
+            .into_inner()
+            .into_iter()
+            .map(|hash| (hash, sighash))
+            .collect()
+    }
+}
+
+// Runs a satisfaction of `desc` that records the keys and key hashes
+// it asks signatures for
+fn probe_signatures<Pk, D>(desc: &D) -> SigProbe<Pk>
+where
+    Pk: MiniscriptKey + ToPublicKey,
+    D: DescriptorTrait<Pk> + ?Sized,
+{
+    let probe = SigProbe {
+        keys: RefCell::new(vec![]),
+        hashes: RefCell::new(vec![]),
+    };
+    let _ = desc.get_satisfaction(&probe);
+    probe
+}
+
+// Records the keys a descriptor asks signatures for without
+// providing any
+struct SigProbe<Pk: MiniscriptKey> {
+    keys: RefCell<Vec<Pk>>,
+    hashes: RefCell<Vec<Pk::Hash>>,
+}
+
+impl<Pk: MiniscriptKey> SigProbe<Pk> {
+    fn record_hash(&self, hash: &Pk::Hash) {
+        let mut hashes = self.hashes.borrow_mut();
+        if !hashes.contains(hash) {
+            hashes.push(hash.clone());
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for SigProbe<Pk> {
+    fn lookup_sig(&self, pk: &Pk) -> Option<ElementsSig> {
+        let mut pks = self.keys.borrow_mut();
+        if !pks.contains(pk) {
+            pks.push(pk.clone());
+        }
+        None
+    }
+
+    fn lookup_pkh_pk(&self, hash: &Pk::Hash) -> Option<Pk> {
+        self.record_hash(hash);
+        None
+    }
+
+    fn lookup_pkh_sig(&self, hash: &Pk::Hash) -> Option<(bitcoin::PublicKey, ElementsSig)> {
+        self.record_hash(hash);
+        None
+    }
 }
 
 /// Descriptor Type of the descriptor
@@ -471,8 +758,34 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
     }
 
     /// Return a string without the checksum
+    pub fn to_string_no_checksum(&self) -> String {
+        format!("{:#}", self)
+    }
+
+    /// Alias for [Descriptor::to_string_no_checksum]
     pub fn to_string_no_chksum(&self) -> String {
-        format!("{:?}", self)
+        self.to_string_no_checksum()
+    }
+}
+
+impl<Pk> Descriptor<Pk>
+where
+    Pk: MiniscriptKey + ToPublicKey + FromStr,
+    Pk::Hash: FromStr,
+    <Pk as FromStr>::Err: ToString,
+    <<Pk as MiniscriptKey>::Hash as FromStr>::Err: ToString,
+{
+    /// The amount below which an explicit output to this descriptor
+    /// is dust at `dust_relay_fee` satoshis per 1000 virtual bytes,
+    /// usually [DUST_RELAY_TX_FEE]. Such outputs are not relayed.
+    pub fn dust_threshold(&self, dust_relay_fee: u64) -> u64 {
+        dust_threshold(&self.script_pubkey(), false, dust_relay_fee)
+    }
+
+    /// Whether an explicit output of `amount` to this descriptor is dust
+    /// at `dust_relay_fee` satoshis per 1000 virtual bytes
+    pub fn is_dust(&self, amount: u64, dust_relay_fee: u64) -> bool {
+        amount < self.dust_threshold(dust_relay_fee)
     }
 }
 
@@ -637,6 +950,22 @@ where
         }
     }
 
+    /// Computes the p2sh redeemScript of the descriptor, if it is
+    /// wrapped in sh.
+    fn redeem_script(&self) -> Option<Script>
+    where
+        Pk: ToPublicKey,
+    {
+        match *self {
+            Descriptor::Sh(ref sh) => sh.redeem_script(),
+            Descriptor::Bare(..)
+            | Descriptor::Pkh(..)
+            | Descriptor::Wpkh(..)
+            | Descriptor::Wsh(..)
+            | Descriptor::Cov(..) => None,
+        }
+    }
+
     /// Returns satisfying witness and scriptSig to spend an
     /// output controlled by the given descriptor if it possible to
     /// construct one using the satisfier S.
@@ -719,6 +1048,69 @@ impl Descriptor<DescriptorPublicKey> {
         self.translate_pk2_infallible(|pk| pk.clone().derive(index))
     }
 
+    /// Derives the descriptor at `index` and converts every key to a
+    /// public key, like deriving it with [Descriptor::derive] and then
+    /// translating its keys with [DescriptorPublicKey::derive_public_key].
+    /// Extended keys derived up to their wildcard are kept in `cache`, so
+    /// that deriving the descriptor at many indices is cheaper.
+    ///
+    /// Will return an error if any key requires hardened derivation.
+    /// Panics if given an index ≥ 2^31
+    pub fn at_derivation_index_cached<C: secp256k1_zkp::Verification>(
+        &self,
+        secp: &secp256k1_zkp::Secp256k1<C>,
+        index: u32,
+        cache: &DerivationCache,
+    ) -> Result<Descriptor<bitcoin::PublicKey>, ConversionError> {
+        self.translate_pk2(|xpk| xpk.derive_public_key_cached(secp, index, cache))
+    }
+
+    /// Derives the descriptor at every index in `range` and returns the
+    /// resulting scriptPubKeys, in order.
+    ///
+    /// Will return an error if any key requires hardened derivation.
+    /// Panics if the range contains an index ≥ 2^31
+    pub fn script_pubkeys<C: secp256k1_zkp::Verification>(
+        &self,
+        secp: &secp256k1_zkp::Secp256k1<C>,
+        range: Range<u32>,
+    ) -> Result<Vec<Script>, ConversionError> {
+        range
+            .map(|index| {
+                let desc = self
+                    .derive(index)
+                    .translate_pk2(|xpk| xpk.derive_public_key(secp))?;
+                Ok(desc.script_pubkey())
+            })
+            .collect()
+    }
+
+    /// Translates every key of the descriptor into a single public key
+    /// using `translatefpk`, keeping the key-origin information of the
+    /// original key. Extended keys are replaced by single keys whose origin
+    /// is the master fingerprint and the full derivation path of the
+    /// extended key, so the result can later be matched against a wallet.
+    pub fn translate_pk_preserving_origins<F, E>(
+        &self,
+        translatefpk: F,
+    ) -> Result<Descriptor<DescriptorPublicKey>, E>
+    where
+        F: Fn(&DescriptorPublicKey) -> Result<bitcoin::PublicKey, E>,
+    {
+        self.translate_pk2(|pk| {
+            let origin = match *pk {
+                DescriptorPublicKey::SinglePub(ref single) => single.origin.clone(),
+                DescriptorPublicKey::XPub(..) => {
+                    Some((pk.master_fingerprint(), pk.full_derivation_path()))
+                }
+            };
+            Ok(DescriptorPublicKey::SinglePub(DescriptorSinglePub {
+                origin,
+                key: translatefpk(pk)?,
+            }))
+        })
+    }
+
     /// Parse a descriptor that may contain secret keys
     ///
     /// Internally turns every secret key found into the corresponding public key and then returns a
@@ -787,12 +1179,15 @@ where
 {
     /// Parse an expression tree into a descriptor
     fn from_tree(top: &expression::Tree) -> Result<Descriptor<Pk>, Error> {
+        let context = |e: Error| e.context(format!("while parsing `{}(...)`", top.name));
         Ok(match (top.name, top.args.len() as u32) {
-            ("elpkh", 1) => Descriptor::Pkh(Pkh::from_tree(top)?),
-            ("elwpkh", 1) => Descriptor::Wpkh(Wpkh::from_tree(top)?),
-            ("elsh", 1) => Descriptor::Sh(Sh::from_tree(top)?),
-            ("elcovwsh", 2) => Descriptor::Cov(CovenantDescriptor::from_tree(top)?),
-            ("elwsh", 1) => Descriptor::Wsh(Wsh::from_tree(top)?),
+            ("elpkh", 1) => Descriptor::Pkh(Pkh::from_tree(top).map_err(context)?),
+            ("elwpkh", 1) => Descriptor::Wpkh(Wpkh::from_tree(top).map_err(context)?),
+            ("elsh", 1) => Descriptor::Sh(Sh::from_tree(top).map_err(context)?),
+            ("elcovwsh", 2) => {
+                Descriptor::Cov(CovenantDescriptor::from_tree(top).map_err(context)?)
+            }
+            ("elwsh", 1) => Descriptor::Wsh(Wsh::from_tree(top).map_err(context)?),
             _ => Descriptor::Bare(Bare::from_tree(top)?),
         })
     }
@@ -808,7 +1203,7 @@ where
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Descriptor<Pk>, Error> {
-        if !s.starts_with(ELMTS_STR) {
+        if !s.trim_start().starts_with(ELMTS_STR) {
             return Err(Error::BadDescriptor(String::from(
                 "Not an Elements Descriptor",
             )));
@@ -819,6 +1214,29 @@ where
     }
 }
 
+impl<Pk> Descriptor<Pk>
+where
+    Pk: MiniscriptKey + str::FromStr,
+    Pk::Hash: str::FromStr,
+    <Pk as FromStr>::Err: ToString,
+    <<Pk as MiniscriptKey>::Hash as FromStr>::Err: ToString,
+{
+    /// Parse a descriptor that may omit the `el` prefix of Elements
+    /// descriptors, e.g. `wpkh(..)` for `elwpkh(..)`. Such strings are
+    /// otherwise rejected by [FromStr] as they are indistinguishable
+    /// from bitcoin descriptors. A checksum, if present, must be the one
+    /// of the string as given. The parsed descriptor is always displayed
+    /// with the prefix and a checksum over the prefixed string.
+    pub fn from_str_allow_unprefixed(s: &str) -> Result<Descriptor<Pk>, Error> {
+        let desc_str = verify_checksum(s)?.trim_start();
+        if desc_str.starts_with(ELMTS_STR) {
+            Descriptor::from_str(desc_str)
+        } else {
+            Descriptor::from_str(&format!("{}{}", ELMTS_STR, desc_str))
+        }
+    }
+}
+
 impl<Pk: MiniscriptKey> fmt::Debug for Descriptor<Pk> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -832,15 +1250,16 @@ impl<Pk: MiniscriptKey> fmt::Debug for Descriptor<Pk> {
     }
 }
 
+/// The alternate form (`{:#}`) omits the checksum
 impl<Pk: MiniscriptKey> fmt::Display for Descriptor<Pk> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Descriptor::Bare(ref sub) => write!(f, "{}", sub),
-            Descriptor::Pkh(ref pkh) => write!(f, "{}", pkh),
-            Descriptor::Wpkh(ref wpkh) => write!(f, "{}", wpkh),
-            Descriptor::Sh(ref sub) => write!(f, "{}", sub),
-            Descriptor::Wsh(ref sub) => write!(f, "{}", sub),
-            Descriptor::Cov(ref cov) => write!(f, "{}", cov),
+            Descriptor::Bare(ref sub) => fmt::Display::fmt(sub, f),
+            Descriptor::Pkh(ref pkh) => fmt::Display::fmt(pkh, f),
+            Descriptor::Wpkh(ref wpkh) => fmt::Display::fmt(wpkh, f),
+            Descriptor::Sh(ref sub) => fmt::Display::fmt(sub, f),
+            Descriptor::Wsh(ref sub) => fmt::Display::fmt(sub, f),
+            Descriptor::Cov(ref cov) => fmt::Display::fmt(cov, f),
         }
     }
 }
@@ -850,15 +1269,17 @@ serde_string_impl_pk!(Descriptor, "a script descriptor");
 #[cfg(test)]
 mod tests {
     use super::checksum::desc_checksum;
-    use super::DescriptorTrait;
+    use super::{DescriptorTrait, ElementsTrait, DUST_RELAY_TX_FEE};
     use bitcoin;
     use bitcoin::hashes::hex::FromHex;
+    use bitcoin::hashes::Hash;
     use bitcoin::hashes::{hash160, sha256};
     use bitcoin::util::bip32;
     use bitcoin::PublicKey;
     use descriptor::key::Wildcard;
     use descriptor::{
-        DescriptorPublicKey, DescriptorSecretKey, DescriptorSinglePub, DescriptorXKey,
+        Bare, ConfidentialDescriptor, ConfidentialKey, ConversionError, DescriptorPublicKey,
+        DescriptorSecretKey, DescriptorSinglePub, DescriptorXKey, Pkh,
     };
 
     use elements::opcodes::{
@@ -869,11 +1290,12 @@ mod tests {
     use elements::{self, secp256k1_zkp};
     use elements::{script, Script};
     use hex_script;
-    use miniscript::satisfy::ElementsSig;
+    use miniscript::context::ScriptContextError;
+    use miniscript::satisfy::{After, ElementsSig, Older, SIGHASH_RANGEPROOF};
     use std::cmp;
     use std::collections::HashMap;
     use std::str::FromStr;
-    use {Descriptor, DummyKey, Error, Miniscript, Satisfier, TranslatePk2};
+    use {Descriptor, DummyKey, Error, Miniscript, MiniscriptKey, Satisfier, TranslatePk2};
 
     #[cfg(feature = "compiler")]
     use policy;
@@ -914,6 +1336,21 @@ mod tests {
         );
     }
 
+    // helper function to create a compressed key and its signature of a
+    // fixed message
+    fn test_key_and_sig() -> (bitcoin::PublicKey, secp256k1_zkp::Signature) {
+        let secp = secp256k1_zkp::Secp256k1::new();
+        let sk =
+            secp256k1_zkp::SecretKey::from_slice(&b"sally was a secret key, she said"[..]).unwrap();
+        let pk = bitcoin::PublicKey {
+            key: secp256k1_zkp::PublicKey::from_secret_key(&secp, &sk),
+            compressed: true,
+        };
+        let msg = secp256k1_zkp::Message::from_slice(&b"michael was a message, amusingly"[..])
+            .expect("32 bytes");
+        (pk, secp.sign(&msg, &sk))
+    }
+
     // helper function to create elements txin from scriptsig and witness
     fn elements_txin(script_sig: Script, witness: Vec<Vec<u8>>) -> elements::TxIn {
         let mut txin_witness = elements::TxInWitness::default();
@@ -948,11 +1385,12 @@ mod tests {
             StdDescriptor::from_str("elsh(sortedmulti)")
                 .unwrap_err()
                 .to_string(),
-            "unexpected «no arguments given for sortedmulti»"
+            "while parsing `elsh(...)` → unexpected «no arguments given for sortedmulti»"
         ); //issue 202
         assert_eq!(
             StdDescriptor::from_str(&format!("elsh(sortedmulti(2,{}))", compressed_pk))
                 .unwrap_err()
+                .root()
                 .to_string(),
             "unexpected «higher threshold than there were keys in sortedmulti»"
         ); //issue 202
@@ -977,6 +1415,23 @@ mod tests {
             uncompressed_pk, uncompressed_pk
         ))
         .unwrap_err();
+
+        // Sanity checks
+        let bare = StdDescriptor::from_str(&format!("elpk({})", uncompressed_pk)).unwrap();
+        bare.sanity_check().unwrap();
+        let pkh = StdDescriptor::from_str(&format!("elpkh({})", uncompressed_pk)).unwrap();
+        pkh.sanity_check().unwrap();
+        let wsh = StdDescriptor::from_str(&format!("elwsh(multi(1,{}))", uncompressed_pk)).unwrap();
+        match wsh.sanity_check() {
+            Err(Error::ContextError(ScriptContextError::CompressedOnly)) => {}
+            res => panic!("Unexpected sanity check result {:?}", res),
+        }
+        let wsh = StdDescriptor::from_str(&format!(
+            "elsh(wsh(sortedmulti(1,{},{})))",
+            "020000000000000000000000000000000000000000000000000000000000000002", uncompressed_pk
+        ))
+        .unwrap();
+        wsh.sanity_check().unwrap_err();
     }
 
     #[test]
@@ -1291,6 +1746,283 @@ mod tests {
         assert_eq!(shwsh.unsigned_script_sig(), expected_ssig);
     }
 
+    #[test]
+    fn address_maybe_blinded() {
+        let pkh = StdDescriptor::from_str(
+            "elpkh(\
+             020000000000000000000000000000000000000000000000000000000000000002\
+             )",
+        )
+        .unwrap();
+        let blinder = secp256k1_zkp::PublicKey::from_str(
+            "03aab896d53a8e7d6433137bbba940f9c521e085dd07e60994579b64a6d992cf79",
+        )
+        .unwrap();
+        let params = &elements::AddressParams::ELEMENTS;
+
+        let explicit = pkh.address_maybe_blinded(None, params).unwrap();
+        assert!(!explicit.is_blinded());
+        assert_eq!(explicit, pkh.address(params).unwrap());
+
+        let confidential = pkh.address_maybe_blinded(Some(blinder), params).unwrap();
+        assert!(confidential.is_blinded());
+        assert_eq!(confidential, pkh.blind_addr(Some(blinder), params).unwrap());
+        assert_eq!(confidential.to_unconfidential(), explicit);
+    }
+
+    #[test]
+    fn concrete_key_address() {
+        // Concrete keys need no context to compute scripts or addresses
+        let pk = bitcoin::PublicKey::from_str(
+            "020000000000000000000000000000000000000000000000000000000000000002",
+        )
+        .unwrap();
+        let pkh = Pkh::new(pk);
+        let params = &elements::AddressParams::ELEMENTS;
+        let addr = pkh.address(params).unwrap();
+        assert_eq!(addr.script_pubkey(), pkh.script_pubkey());
+        assert_eq!(addr, elements::Address::p2pkh(&pk, None, params));
+        assert_eq!(Descriptor::Pkh(pkh).address(params).unwrap(), addr);
+    }
+
+    #[test]
+    fn redeem_script() {
+        let shwsh = StdDescriptor::from_str(
+            "elsh(wsh(multi(2,\
+             020000000000000000000000000000000000000000000000000000000000000002,\
+             03aab896d53a8e7d6433137bbba940f9c521e085dd07e60994579b64a6d992cf79\
+             )))",
+        )
+        .unwrap();
+        let witness_script = shwsh.explicit_script();
+        let redeem_script = shwsh.redeem_script().unwrap();
+        assert_eq!(redeem_script, witness_script.to_v0_p2wsh());
+        assert_eq!(redeem_script.len(), 34);
+        assert_eq!(redeem_script[..][0], opcodes::all::OP_PUSHBYTES_0.into_u8());
+        assert_eq!(
+            redeem_script[..][1],
+            opcodes::all::OP_PUSHBYTES_32.into_u8()
+        );
+        assert_eq!(
+            &redeem_script[2..],
+            &sha256::Hash::hash(&witness_script[..])[..]
+        );
+        assert_eq!(shwsh.script_pubkey(), redeem_script.to_p2sh());
+
+        // The unsigned scriptSig pushes exactly the redeem script
+        let script_sig = shwsh.unsigned_script_sig();
+        assert_eq!(script_sig.len(), 35);
+        assert_eq!(script_sig[..][0], opcodes::all::OP_PUSHBYTES_34.into_u8());
+        assert_eq!(&script_sig[1..], &redeem_script[..]);
+
+        let wsh = StdDescriptor::from_str(
+            "elwsh(multi(2,\
+             020000000000000000000000000000000000000000000000000000000000000002,\
+             03aab896d53a8e7d6433137bbba940f9c521e085dd07e60994579b64a6d992cf79\
+             ))",
+        )
+        .unwrap();
+        assert_eq!(wsh.redeem_script(), None);
+        assert_eq!(wsh.explicit_script(), witness_script);
+    }
+
+    #[test]
+    fn max_satisfaction_vsize() {
+        let pkh = StdDescriptor::from_str(
+            "elpkh(\
+             020000000000000000000000000000000000000000000000000000000000000002\
+             )",
+        )
+        .unwrap();
+        // 4 * (1 + 73 + 34) = 432 weight units
+        assert_eq!(pkh.max_satisfaction_weight().unwrap(), 432);
+        assert_eq!(pkh.max_satisfaction_vsize().unwrap(), 108);
+
+        let wsh = StdDescriptor::from_str(
+            "elwsh(pk(\
+             020000000000000000000000000000000000000000000000000000000000000002\
+             ))",
+        )
+        .unwrap();
+        // 114 weight units is 28.5 vbytes, which must be rounded up
+        assert_eq!(wsh.max_satisfaction_weight().unwrap(), 114);
+        assert_eq!(wsh.max_satisfaction_vsize().unwrap(), 29);
+    }
+
+    #[test]
+    fn satisfy_pkh_by_hash() {
+        let (pk, sig) = test_key_and_sig();
+        let mut sigser = sig.serialize_der().to_vec();
+        sigser.push(0x01); // sighash_all
+
+        // Only knows signatures by the hash of the key
+        struct PkhSat {
+            map: HashMap<hash160::Hash, (bitcoin::PublicKey, ElementsSig)>,
+        }
+
+        impl Satisfier<bitcoin::PublicKey> for PkhSat {
+            fn lookup_pkh_sig(
+                &self,
+                pkh: &hash160::Hash,
+            ) -> Option<(bitcoin::PublicKey, ElementsSig)> {
+                self.map.get(pkh).cloned()
+            }
+        }
+
+        let pkh = Descriptor::new_pkh(pk);
+        let mut map = HashMap::new();
+        let satisfier = PkhSat { map: map.clone() };
+        match pkh.get_satisfaction(&satisfier) {
+            Err(Error::MissingSig(missing)) => assert_eq!(missing, pk),
+            _ => panic!("Satisfaction must fail without the signature"),
+        }
+
+        map.insert(pk.to_pubkeyhash(), (pk, (sig, elements::SigHashType::All)));
+        let satisfier = PkhSat { map };
+        let (witness, script_sig) = pkh.get_satisfaction(&satisfier).unwrap();
+        assert!(witness.is_empty());
+        assert_eq!(
+            script_sig,
+            script::Builder::new()
+                .push_slice(&sigser[..])
+                .push_key(&pk)
+                .into_script()
+        );
+    }
+
+    #[test]
+    fn satisfy_preferred_sighash() {
+        let (pk, sig) = test_key_and_sig();
+
+        struct SighashSat {
+            sig: ElementsSig,
+            preferred: Option<u32>,
+        }
+
+        impl Satisfier<bitcoin::PublicKey> for SighashSat {
+            fn lookup_sig(&self, _: &bitcoin::PublicKey) -> Option<ElementsSig> {
+                Some(self.sig)
+            }
+
+            fn preferred_sighash(&self) -> Option<u32> {
+                self.preferred
+            }
+        }
+
+        let wpkh = Descriptor::new_wpkh(pk).unwrap();
+        let wsh =
+            Descriptor::<bitcoin::PublicKey>::from_str(&format!("elwsh(pk({}))", pk)).unwrap();
+        let sat = |preferred| SighashSat {
+            sig: (sig, elements::SigHashType::All),
+            preferred,
+        };
+        let last_byte = |witness: Vec<Vec<u8>>| *witness[0].last().unwrap();
+
+        // No preference keeps the signature as is
+        let (witness, _) = wpkh.get_satisfaction(sat(None)).unwrap();
+        assert_eq!(last_byte(witness), 0x01);
+
+        // Matching preference, with and without the rangeproof flag
+        let (witness, _) = wpkh.get_satisfaction(sat(Some(0x01))).unwrap();
+        assert_eq!(last_byte(witness), 0x01);
+        let rangeproof = Some(0x01 | SIGHASH_RANGEPROOF);
+        let (witness, _) = wpkh.get_satisfaction(sat(rangeproof)).unwrap();
+        assert_eq!(last_byte(witness), 0x41);
+        let (witness, _) = wsh.get_satisfaction(sat(rangeproof)).unwrap();
+        assert_eq!(last_byte(witness), 0x41);
+
+        // Signatures with another sighash type are skipped
+        match wpkh.get_satisfaction(sat(Some(0x03))) {
+            Err(Error::MissingSig(missing)) => assert_eq!(missing, pk),
+            res => panic!("unexpected {:?}", res),
+        }
+        match wsh.get_satisfaction(sat(Some(0x83 | SIGHASH_RANGEPROOF))) {
+            Err(Error::CouldNotSatisfy) => {}
+            res => panic!("unexpected {:?}", res),
+        }
+    }
+
+    #[test]
+    fn check_standardness() {
+        let pk = "020000000000000000000000000000000000000000000000000000000000000002";
+        for desc in &[
+            format!("elwsh(multi(1,{}))", pk),
+            format!("elsh(wsh(multi(1,{})))", pk),
+            format!("elpkh({})", pk),
+        ] {
+            StdDescriptor::from_str(desc)
+                .unwrap()
+                .check_standardness()
+                .unwrap();
+        }
+
+        // 3 * 70 SWAP CHECKSIG ADDs, plus the first CHECKSIG and the EQUAL
+        let subs = vec![format!("s:pk({})", pk); 70].join(",");
+        let desc = format!("elwsh(thresh(1,pk({}),{}))", pk, subs);
+        let desc = StdDescriptor::from_str(&desc).unwrap();
+        match desc.check_standardness() {
+            Err(Error::StandardOpCountExceeded(212)) => {}
+            res => panic!("unexpected {:?}", res),
+        }
+    }
+
+    #[test]
+    fn required_signatures() {
+        let pks: Vec<PublicKey> = (2..5)
+            .map(|i| {
+                PublicKey::from_str(&format!(
+                    "02000000000000000000000000000000000000000000000000000000000000000{}",
+                    i
+                ))
+                .unwrap()
+            })
+            .collect();
+        let all = |pks: &[PublicKey]| -> Vec<_> {
+            pks.iter()
+                .map(|pk| (*pk, elements::SigHashType::All))
+                .collect()
+        };
+
+        // Every key of a 2-of-3 is a candidate
+        let multi =
+            StdDescriptor::from_str(&format!("elwsh(multi(2,{},{},{}))", pks[0], pks[1], pks[2]))
+                .unwrap();
+        assert_eq!(
+            multi.required_signatures(elements::SigHashType::All),
+            all(&pks)
+        );
+        assert!(multi
+            .required_key_hash_signatures(elements::SigHashType::All)
+            .is_empty());
+
+        let pkh = Descriptor::new_pkh(pks[1]);
+        assert_eq!(
+            pkh.required_signatures(elements::SigHashType::All),
+            all(&pks[1..2])
+        );
+
+        let hashlock = StdDescriptor::from_str(&format!(
+            "elwsh(and_v(v:sha256({}),pk({})))",
+            "0000000000000000000000000000000000000000000000000000000000000000", pks[2]
+        ))
+        .unwrap();
+        assert_eq!(
+            hashlock.required_signatures(elements::SigHashType::All),
+            all(&pks[2..])
+        );
+
+        // pkh fragments only know the hash of their key
+        let wsh_pkh =
+            StdDescriptor::from_str(&format!("elwsh(pkh({}))", pks[0].to_pubkeyhash())).unwrap();
+        assert!(wsh_pkh
+            .required_signatures(elements::SigHashType::All)
+            .is_empty());
+        assert_eq!(
+            wsh_pkh.required_key_hash_signatures(elements::SigHashType::Single),
+            vec![(pks[0].to_pubkeyhash(), elements::SigHashType::Single)]
+        );
+    }
+
     #[test]
     fn after_is_cltv() {
         let descriptor = Descriptor::<bitcoin::PublicKey>::from_str("elwsh(after(1000))").unwrap();
@@ -1302,6 +2034,79 @@ mod tests {
         assert_eq!(check, &Ok(Instruction::Op(OP_CLTV)))
     }
 
+    #[test]
+    fn satisfaction_timelocks() {
+        let (pk, sig) = test_key_and_sig();
+        let mut sigs = HashMap::<bitcoin::PublicKey, ElementsSig>::new();
+        sigs.insert(pk, (sig, elements::SigHashType::All));
+
+        let csv =
+            StdDescriptor::from_str(&format!("elwsh(and_v(v:older(144),pk({})))", pk)).unwrap();
+        let sat = csv
+            .get_satisfaction_with_timelocks((&sigs, Older(144)))
+            .unwrap();
+        assert_eq!(
+            (sat.witness.clone(), sat.script_sig.clone()),
+            csv.get_satisfaction((&sigs, Older(144))).unwrap()
+        );
+        assert_eq!(sat.sequence, 144);
+        assert_eq!(sat.lock_time, 0);
+
+        // Only the chosen branch contributes to the timelocks
+        let or = StdDescriptor::from_str(&format!(
+            "elwsh(or_d(pk({}),and_v(v:pk({}),older(1000))))",
+            pk, "020000000000000000000000000000000000000000000000000000000000000002"
+        ))
+        .unwrap();
+        let sat = or.get_satisfaction_with_timelocks(&sigs).unwrap();
+        assert_eq!((sat.lock_time, sat.sequence), (0, 0xffffffff));
+
+        let cltv =
+            StdDescriptor::from_str(&format!("elwsh(and_v(v:after(500),pk({})))", pk)).unwrap();
+        let sat = cltv
+            .get_satisfaction_with_timelocks((&sigs, After(500)))
+            .unwrap();
+        assert_eq!((sat.lock_time, sat.sequence), (500, 0xfffffffe));
+
+        // A wrong preimage is only caught by the interpreter, whose
+        // error is reported as is
+        struct WrongPreimage;
+        impl Satisfier<bitcoin::PublicKey> for WrongPreimage {
+            fn lookup_sha256(&self, _: sha256::Hash) -> Option<[u8; 32]> {
+                Some([0; 32])
+            }
+        }
+        let hash = sha256::Hash::hash(&[1; 32]);
+        let desc = StdDescriptor::from_str(&format!("elwsh(sha256({}))", hash)).unwrap();
+        match desc.get_satisfaction_with_timelocks(WrongPreimage) {
+            Err(Error::InterpreterError(..)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn satisfy_older() {
+        let (pk, sig) = test_key_and_sig();
+        let mut sigser = sig.serialize_der().to_vec();
+        sigser.push(0x01); // sighash_all
+
+        let mut sigs = HashMap::new();
+        sigs.insert(pk, (sig, elements::SigHashType::All));
+
+        let desc = Descriptor::<bitcoin::PublicKey>::from_str(&format!(
+            "elwsh(and_v(v:older(144),pk({})))",
+            pk
+        ))
+        .unwrap();
+
+        // The satisfier must approve the relative timelock
+        assert!(desc.get_satisfaction(&sigs).is_err());
+        assert!(desc.get_satisfaction((&sigs, Older(143))).is_err());
+        let (witness, script_sig) = desc.get_satisfaction((&sigs, Older(144))).unwrap();
+        assert_eq!(script_sig, Script::new());
+        assert_eq!(witness, vec![sigser, desc.explicit_script().into_bytes()]);
+    }
+
     #[test]
     fn older_is_csv() {
         let descriptor = Descriptor::<bitcoin::PublicKey>::from_str("elwsh(older(1000))").unwrap();
@@ -1584,6 +2389,222 @@ mod tests {
         );
     }
 
+    #[test]
+    fn sortedmulti_key_order_and_limit() {
+        use policy::Liftable;
+        use TranslatePk;
+
+        let secp = secp256k1_zkp::Secp256k1::new();
+        let pks: Vec<bitcoin::PublicKey> = (1..22u8)
+            .map(|i| bitcoin::PublicKey {
+                key: secp256k1_zkp::PublicKey::from_secret_key(
+                    &secp,
+                    &secp256k1_zkp::SecretKey::from_slice(&[i; 32]).unwrap(),
+                ),
+                compressed: true,
+            })
+            .collect();
+
+        // Key order affects neither the script, the lifted policy nor translation
+        let mut rev = pks[..3].to_vec();
+        rev.reverse();
+        let one = Descriptor::new_wsh_sortedmulti(2, pks[..3].to_vec()).unwrap();
+        let two = Descriptor::new_wsh_sortedmulti(2, rev).unwrap();
+        assert_ne!(one, two);
+        assert_eq!(one.script_pubkey(), two.script_pubkey());
+        assert_eq!(one.lift().unwrap().sorted(), two.lift().unwrap().sorted());
+        let one_str = one
+            .translate_pk(
+                |pk| Ok::<_, ()>(pk.to_string()),
+                |pkh| Ok::<_, ()>(pkh.to_string()),
+            )
+            .unwrap();
+        assert_eq!(one_str.to_string(), one.to_string());
+
+        // CHECKMULTISIG takes at most 20 keys, even where the script size
+        // would allow more
+        assert!(Descriptor::new_wsh_sortedmulti(1, pks.clone()).is_err());
+        assert!(Descriptor::new_wsh_sortedmulti(1, pks[..20].to_vec()).is_ok());
+        let desc = format!(
+            "elwsh(sortedmulti(1,{}))",
+            pks.iter()
+                .map(|pk| pk.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        assert!(Descriptor::<bitcoin::PublicKey>::from_str(&desc).is_err());
+    }
+
+    #[test]
+    fn dust_threshold() {
+        let desc = StdDescriptor::from_str(
+            "elwpkh(020000000000000000000000000000000000000000000000000000000000000002)",
+        )
+        .unwrap();
+        // 66 byte output and 67 byte segwit spend at 3 sat/vbyte
+        assert_eq!(desc.dust_threshold(DUST_RELAY_TX_FEE), 399);
+        assert!(desc.is_dust(398, DUST_RELAY_TX_FEE));
+        assert!(!desc.is_dust(399, DUST_RELAY_TX_FEE));
+
+        // Commitments and proofs add 1117 virtual bytes
+        let ct = ConfidentialDescriptor::new(
+            ConfidentialKey::Bare(
+                PublicKey::from_str(
+                    "020000000000000000000000000000000000000000000000000000000000000002",
+                )
+                .unwrap(),
+            ),
+            desc.clone(),
+        );
+        assert_eq!(ct.dust_threshold(DUST_RELAY_TX_FEE), 3750);
+        assert!(ct.is_dust(3749, DUST_RELAY_TX_FEE));
+        assert!(!ct.is_dust(3750, DUST_RELAY_TX_FEE));
+
+        // Outputs that cannot be spent are never dust
+        let op_return = script::Builder::new()
+            .push_opcode(opcodes::all::OP_RETURN)
+            .into_script();
+        assert_eq!(
+            super::dust_threshold(&op_return, true, DUST_RELAY_TX_FEE),
+            0
+        );
+    }
+
+    #[test]
+    fn confidential_descriptor_trait() {
+        let secp = secp256k1_zkp::Secp256k1::signing_only();
+        let ct = ConfidentialDescriptor::<PublicKey>::from_str(
+            "ct(slip77(b2396b3ee20509cdb64fe24180a14a72dbd671728eaa49bac69d2bdecb5f5a04),\
+             elwpkh(020000000000000000000000000000000000000000000000000000000000000002))",
+        )
+        .unwrap();
+        let params = &elements::AddressParams::ELEMENTS;
+        let addr = DescriptorTrait::address(&ct, params).unwrap();
+        assert_eq!(addr, ct.address(&secp, params).unwrap());
+        assert!(addr.is_blinded());
+        assert_eq!(addr.script_pubkey(), ct.script_pubkey());
+        assert_eq!(
+            ct.max_satisfaction_weight().unwrap(),
+            ct.as_explicit().max_satisfaction_weight().unwrap()
+        );
+        assert_eq!(
+            ::policy::Liftable::lift(&ct).unwrap(),
+            ::policy::Liftable::lift(ct.as_explicit()).unwrap()
+        );
+    }
+
+    #[test]
+    fn unblind_txout() {
+        use elements::confidential::{Asset, AssetBlindingFactor, Value, ValueBlindingFactor};
+        use elements::secp256k1_zkp::rand::thread_rng;
+
+        let secp = secp256k1_zkp::Secp256k1::new();
+        let ct = ConfidentialDescriptor::<PublicKey>::from_str(
+            "ct(slip77(b2396b3ee20509cdb64fe24180a14a72dbd671728eaa49bac69d2bdecb5f5a04),\
+             elwpkh(020000000000000000000000000000000000000000000000000000000000000002))",
+        )
+        .unwrap();
+        let asset = elements::AssetId::from_slice(&[7; 32]).unwrap();
+
+        // Received unblinded
+        let mut txout = elements::TxOut {
+            asset: Asset::Explicit(asset),
+            value: Value::Explicit(5_000),
+            nonce: elements::confidential::Nonce::Null,
+            script_pubkey: ct.as_explicit().script_pubkey(),
+            witness: elements::TxOutWitness::default(),
+        };
+        assert!(ct.matches_txout(&txout));
+        let secrets = ct.unblind(&secp, &txout).unwrap();
+        assert_eq!((secrets.asset, secrets.value), (asset, 5_000));
+        assert_eq!(secrets.asset_bf, AssetBlindingFactor::zero());
+        assert_eq!(secrets.value_bf, ValueBlindingFactor::zero());
+
+        // Other scriptPubKeys are rejected
+        txout.script_pubkey = Script::new();
+        assert!(!ct.matches_txout(&txout));
+        assert!(ct.unblind(&secp, &txout).is_err());
+
+        // Received blinded
+        let spent_secrets = elements::TxOutSecrets::new(
+            asset,
+            AssetBlindingFactor::new(&mut thread_rng()),
+            5_000,
+            ValueBlindingFactor::new(&mut thread_rng()),
+        );
+        let spent_asset = Asset::new_confidential(&secp, asset, spent_secrets.asset_bf);
+        let (txout, _, _) = elements::TxOut::new_not_last_confidential(
+            &mut thread_rng(),
+            &secp,
+            4_000,
+            ct.address(&secp, &elements::AddressParams::ELEMENTS)
+                .unwrap(),
+            asset,
+            &[(spent_asset, Some(&spent_secrets))],
+        )
+        .unwrap();
+        assert!(ct.matches_txout(&txout));
+        let secrets = ct.unblind(&secp, &txout).unwrap();
+        assert_eq!((secrets.asset, secrets.value), (asset, 4_000));
+
+        // A bare blinding public key cannot unblind it
+        let bare_ct = ConfidentialDescriptor::new(
+            ConfidentialKey::Bare(PublicKey {
+                key: ct.blinding_pubkey(&secp),
+                compressed: true,
+            }),
+            ct.as_explicit().clone(),
+        );
+        assert!(bare_ct.unblind(&secp, &txout).is_err());
+    }
+
+    #[test]
+    fn pkh_script_sig() {
+        let secp = secp256k1_zkp::Secp256k1::new();
+        let sk = secp256k1_zkp::SecretKey::from_slice(&[1; 32]).unwrap();
+        let pk = bitcoin::PublicKey {
+            key: secp256k1_zkp::PublicKey::from_secret_key(&secp, &sk),
+            compressed: true,
+        };
+        let sig = secp.sign(&secp256k1_zkp::Message::from_slice(&[2; 32]).unwrap(), &sk);
+        let mut sigs = HashMap::new();
+        sigs.insert(pk, (sig, elements::SigHashType::SinglePlusAnyoneCanPay));
+
+        // DER signature, sighash byte and key
+        let mut raw_sig = sig.serialize_der().to_vec();
+        raw_sig.push(0x83);
+        assert_eq!(
+            ::elementssig_to_rawsig(&(sig, elements::SigHashType::SinglePlusAnyoneCanPay)),
+            raw_sig
+        );
+        let (witness, script_sig) = Descriptor::new_pkh(pk).get_satisfaction(&sigs).unwrap();
+        assert!(witness.is_empty());
+        assert_eq!(
+            script_sig,
+            script::Builder::new()
+                .push_slice(&raw_sig)
+                .push_key(&pk)
+                .into_script()
+        );
+    }
+
+    #[test]
+    fn pkh_as_miniscript() {
+        use policy::Liftable;
+
+        let pkh = Pkh::new(
+            PublicKey::from_str(
+                "020000000000000000000000000000000000000000000000000000000000000002",
+            )
+            .unwrap(),
+        );
+        let ms = pkh.as_miniscript();
+        assert_eq!(ms.encode(), pkh.script_pubkey());
+        assert_eq!(ms.encode(), pkh.explicit_script());
+        assert!(ms.sanity_check().is_ok());
+        assert_eq!(ms.lift().unwrap(), pkh.lift().unwrap());
+    }
+
     #[test]
     fn test_parse_descriptor() {
         let secp = &secp256k1_zkp::Secp256k1::signing_only();
@@ -1643,6 +2664,161 @@ pk(03f28773c2d975288bc7d1d205c3748651b075fbc6610e58cddeeddf8f19405aa8))";
         assert_eq!(res_descriptor, derived_descriptor);
     }
 
+    #[test]
+    fn prefixed_names_roundtrip() {
+        let pk = "020000000000000000000000000000000000000000000000000000000000000002";
+        for name in &["pkh", "wpkh"] {
+            let prefixed = format!("el{}({})", name, pk);
+            let desc = StdDescriptor::from_str(&prefixed).unwrap();
+            let with_checksum = format!("{}#{}", prefixed, desc_checksum(&prefixed).unwrap());
+            assert_eq!(desc.to_string(), with_checksum);
+            assert_eq!(StdDescriptor::from_str(&with_checksum).unwrap(), desc);
+
+            // The unprefixed spelling is only accepted on request
+            let unprefixed = format!("{}({})", name, pk);
+            StdDescriptor::from_str(&unprefixed).unwrap_err();
+            assert_eq!(
+                StdDescriptor::from_str_allow_unprefixed(&unprefixed).unwrap(),
+                desc
+            );
+            let unprefixed = format!("{}#{}", unprefixed, desc_checksum(&unprefixed).unwrap());
+            let parsed = StdDescriptor::from_str_allow_unprefixed(&unprefixed).unwrap();
+            assert_eq!(parsed.to_string(), with_checksum);
+            // A checksum over the prefixed string does not match the unprefixed one
+            let mismatched = format!("{}({})#{}", name, pk, desc_checksum(&prefixed).unwrap());
+            StdDescriptor::from_str_allow_unprefixed(&mismatched).unwrap_err();
+            assert_eq!(
+                StdDescriptor::from_str_allow_unprefixed(&with_checksum).unwrap(),
+                desc
+            );
+        }
+        let nested = format!("sh(wsh(multi(1,{})))", pk);
+        assert_eq!(
+            StdDescriptor::from_str_allow_unprefixed(&nested)
+                .unwrap()
+                .to_string_no_checksum(),
+            format!("el{}", nested)
+        );
+    }
+
+    #[test]
+    fn bare_pkh_hash_map_lookup() {
+        let pk = "020000000000000000000000000000000000000000000000000000000000000002";
+        let bare = Bare::<PublicKey>::from_str(&format!("elpk({})", pk)).unwrap();
+        let pkh = Pkh::<PublicKey>::from_str(&format!("elpkh({})", pk)).unwrap();
+
+        let mut bare_cache = HashMap::new();
+        bare_cache.insert(bare.clone(), 1);
+        let other = Bare::<PublicKey>::from_str(
+            "elpk(020000000000000000000000000000000000000000000000000000000000000003)",
+        )
+        .unwrap();
+        bare_cache.insert(other.clone(), 2);
+        let mut pkh_cache = HashMap::new();
+        pkh_cache.insert(pkh.clone(), 1);
+
+        let reparsed = Bare::<PublicKey>::from_str(&bare.to_string()).unwrap();
+        assert_eq!(bare_cache.get(&reparsed), Some(&1));
+        let reparsed = Bare::<PublicKey>::from_str(&other.to_string()).unwrap();
+        assert_eq!(bare_cache.get(&reparsed), Some(&2));
+        let reparsed = Pkh::<PublicKey>::from_str(&pkh.to_string()).unwrap();
+        assert_eq!(pkh_cache.get(&reparsed), Some(&1));
+    }
+
+    #[test]
+    fn display_no_checksum() {
+        for desc_str in &[
+            "elpkh(020000000000000000000000000000000000000000000000000000000000000002)",
+            "elwpkh(020000000000000000000000000000000000000000000000000000000000000002)",
+            "elsh(wpkh(020000000000000000000000000000000000000000000000000000000000000002))",
+            "elwsh(multi(1,020000000000000000000000000000000000000000000000000000000000000002))",
+            "elcovwsh(020000000000000000000000000000000000000000000000000000000000000002,1)",
+        ] {
+            let desc = StdDescriptor::from_str(desc_str).unwrap();
+            let full = desc.to_string();
+            assert!(full.contains('#'));
+            assert_eq!(format!("{:#}", desc), *desc_str);
+            assert_eq!(desc.to_string_no_checksum(), *desc_str);
+            assert_eq!(
+                format!("{}#{}", desc_str, desc_checksum(desc_str).unwrap()),
+                full
+            );
+            assert_eq!(
+                StdDescriptor::from_str(&format!("{:#}", desc)).unwrap(),
+                desc
+            );
+        }
+    }
+
+    #[test]
+    fn translate_pk_preserving_origins() {
+        let secp = secp256k1_zkp::Secp256k1::verification_only();
+        let desc = Descriptor::<DescriptorPublicKey>::from_str(
+            "elwpkh([d34db33f/44'/0'/0']xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL/1/*)",
+        )
+        .unwrap();
+        let derived = desc.derive(5);
+        assert!(derived
+            .to_string()
+            .starts_with("elwpkh([d34db33f/44'/0'/0']xpub6ERAp"));
+
+        let single = derived
+            .translate_pk_preserving_origins(|xpk| xpk.derive_public_key(&secp))
+            .unwrap();
+        let pk = derived
+            .translate_pk2(|xpk| xpk.derive_public_key(&secp))
+            .unwrap();
+        let pk_str = match pk {
+            Descriptor::Wpkh(ref wpkh) => wpkh.as_inner().to_string(),
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            single.to_string_no_checksum(),
+            format!("elwpkh([d34db33f/44'/0'/0'/1/5]{})", pk_str)
+        );
+        assert_eq!(
+            single
+                .translate_pk2(|xpk| xpk.derive_public_key(&secp))
+                .unwrap(),
+            pk
+        );
+
+        // Errors from the translation function are forwarded
+        assert!(desc
+            .translate_pk_preserving_origins(|xpk| xpk.derive_public_key(&secp))
+            .is_err());
+    }
+
+    #[test]
+    fn script_pubkeys_over_range() {
+        let secp = secp256k1_zkp::Secp256k1::verification_only();
+        let desc = Descriptor::<DescriptorPublicKey>::from_str(
+            "elsh(wsh(multi(1,xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL/1/*,020000000000000000000000000000000000000000000000000000000000000002)))",
+        )
+        .unwrap();
+        let spks = desc.script_pubkeys(&secp, 3..8).unwrap();
+        assert_eq!(spks.len(), 5);
+        for (spk, i) in spks.iter().zip(3..8) {
+            let expected = desc
+                .derive(i)
+                .translate_pk2(|xpk| xpk.derive_public_key(&secp))
+                .unwrap()
+                .script_pubkey();
+            assert_eq!(*spk, expected);
+        }
+        assert_ne!(spks[0], spks[1]);
+        assert!(desc.script_pubkeys(&secp, 0..0).unwrap().is_empty());
+
+        let hardened = Descriptor::<DescriptorPublicKey>::from_str(
+            "elwpkh(xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL/1/*')",
+        )
+        .unwrap();
+        assert_eq!(
+            hardened.script_pubkeys(&secp, 0..2),
+            Err(ConversionError::HardenedChild)
+        );
+    }
+
     #[test]
     fn parse_with_secrets() {
         let secp = &secp256k1_zkp::Secp256k1::signing_only();
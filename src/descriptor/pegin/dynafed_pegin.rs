@@ -48,9 +48,9 @@ use {DescriptorTrait, Segwitv0, TranslatePk};
 
 use {tweak_key, util::varint_len};
 
-use descriptor::checksum::{desc_checksum, verify_checksum};
+use descriptor::checksum::{strip_checksum, verify_checksum, write_with_checksum};
 
-use super::PeginTrait;
+use super::{pegin_tweak_from_user_descriptor, PeginError, PeginTrait};
 use {MiniscriptKey, ToPublicKey};
 
 /// New Pegin Descriptor with Miniscript support
@@ -81,9 +81,9 @@ impl<Pk: MiniscriptKey> fmt::Debug for Pegin<Pk> {
 
 impl<Pk: MiniscriptKey> fmt::Display for Pegin<Pk> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let desc = format!("pegin({},{})", self.fed_desc, self.elem_desc);
-        let checksum = desc_checksum(&desc).map_err(|_| fmt::Error)?;
-        write!(f, "{}#{}", &desc, &checksum)
+        let fed_desc = self.fed_desc.to_string();
+        let desc = format!("pegin({},{:#})", strip_checksum(&fed_desc), self.elem_desc);
+        write_with_checksum(f, &desc)
     }
 }
 
@@ -112,12 +112,15 @@ where
             // a roundtrip hack to use FromTree from bitcoin::Miniscript from
             // expression::Tree in elements.
             let ms_str = top.args[0].to_string();
-            let ms_expr = BtcTree::from_str(&ms_str)?;
+            let ms_expr =
+                BtcTree::from_str(&ms_str).map_err(|e| expression::arg_error(top, 0, e))?;
             //
             // TODO: Confirm with Andrew about the descriptor type for dynafed
             // Assuming sh(wsh) for now.
-            let fed_desc = BtcDescriptor::<Pk>::from_tree(&ms_expr)?;
-            let elem_desc = Descriptor::<Pk>::from_tree(&top.args[1])?;
+            let fed_desc = BtcDescriptor::<Pk>::from_tree(&ms_expr)
+                .map_err(|e| expression::arg_error(top, 0, e))?;
+            let elem_desc = Descriptor::<Pk>::from_tree(&top.args[1])
+                .map_err(|e| expression::arg_error(top, 1, e))?;
             Ok(Pegin::new(fed_desc, elem_desc))
         } else {
             Err(Error::Unexpected(format!(
@@ -171,7 +174,7 @@ where
         Pk: ToPublicKey,
     {
         Ok(bitcoin::Address::p2shwsh(
-            &self.bitcoin_witness_script(secp),
+            &self.bitcoin_witness_script(secp)?,
             network,
         ))
     }
@@ -179,43 +182,41 @@ where
     fn bitcoin_script_pubkey<C: secp256k1_zkp::Verification>(
         &self,
         secp: &secp256k1_zkp::Secp256k1<C>,
-    ) -> BtcScript
+    ) -> Result<BtcScript, Error>
     where
         Pk: ToPublicKey,
     {
-        self.bitcoin_address(bitcoin::Network::Bitcoin, secp)
-            .expect("Address cannot fail for pegin")
-            .script_pubkey()
+        // p2sh scriptpubkeys do not depend on the network
+        Ok(self.bitcoin_witness_script(secp)?.to_v0_p2wsh().to_p2sh())
     }
 
     fn bitcoin_unsigned_script_sig<C: secp256k1_zkp::Verification>(
         &self,
         secp: &secp256k1_zkp::Secp256k1<C>,
-    ) -> BtcScript
+    ) -> Result<BtcScript, Error>
     where
         Pk: ToPublicKey,
     {
-        let witness_script = self.bitcoin_witness_script(secp);
-        script::Builder::new()
+        let witness_script = self.bitcoin_witness_script(secp)?;
+        Ok(script::Builder::new()
             .push_slice(&witness_script.to_v0_p2wsh()[..])
-            .into_script()
+            .into_script())
     }
 
     fn bitcoin_witness_script<C: secp256k1_zkp::Verification>(
         &self,
         secp: &secp256k1_zkp::Secp256k1<C>,
-    ) -> BtcScript
+    ) -> Result<BtcScript, Error>
     where
         Pk: ToPublicKey,
     {
-        let tweak_vec = self.elem_desc.explicit_script().into_bytes();
-        let tweak = hashes::sha256::Hash::hash(&tweak_vec);
-        let tweaked_desc = self.fed_desc.translate_pk_infallible(
-            |pk| tweak_key(pk, secp, tweak.as_inner()),
-            |_| unreachable!("No keyhashes in elements descriptors"),
-        );
+        let tweak = pegin_tweak_from_user_descriptor(&self.elem_desc);
+        let tweaked_desc = self.fed_desc.translate_pk(
+            |pk| Ok(tweak_key(pk, secp, tweak.as_inner())),
+            |_| Err(PeginError::UnsupportedKeyHash),
+        )?;
         // Hopefully, we never have to use this and dynafed is deployed
-        tweaked_desc.explicit_script()
+        Ok(tweaked_desc.explicit_script())
     }
 
     fn get_bitcoin_satisfaction<S, C: secp256k1_zkp::Verification>(
@@ -227,12 +228,11 @@ where
         S: BtcSatisfier<bitcoin::PublicKey>,
         Pk: ToPublicKey,
     {
-        let tweak_vec = self.elem_desc.explicit_script().into_bytes();
-        let tweak = hashes::sha256::Hash::hash(&tweak_vec);
-        let tweaked_desc = self.fed_desc.translate_pk_infallible(
-            |pk| tweak_key(pk, secp, tweak.as_inner()),
-            |_| unreachable!("No keyhashes in elements descriptors"),
-        );
+        let tweak = pegin_tweak_from_user_descriptor(&self.elem_desc);
+        let tweaked_desc = self.fed_desc.translate_pk(
+            |pk| Ok(tweak_key(pk, secp, tweak.as_inner())),
+            |_| Err(PeginError::UnsupportedKeyHash),
+        )?;
         let res = tweaked_desc.get_satisfaction(satisfier)?;
         Ok(res)
     }
@@ -243,10 +243,14 @@ where
         Ok(w)
     }
 
+    fn elements_spend_weight(&self) -> Option<usize> {
+        self.elem_desc.max_satisfaction_weight().ok()
+    }
+
     fn script_code<C: secp256k1_zkp::Verification>(
         &self,
         secp: &secp256k1_zkp::Secp256k1<C>,
-    ) -> BtcScript
+    ) -> Result<BtcScript, Error>
     where
         Pk: ToPublicKey,
     {
@@ -0,0 +1,69 @@
+// Miniscript
+// Written in 2021 by rust-miniscript developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+//! Pegin Descriptor Errors
+
+use std::{error, fmt};
+use Error;
+/// Pegin related Errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PeginError {
+    /// The user descriptor has no address and thus
+    /// cannot receive the claimed funds
+    UnspendableUserDescriptor,
+    /// The user descriptor failed its sanity check
+    InsaneUserDescriptor,
+    /// The federation script contains a key hash, which
+    /// cannot be tweaked
+    UnsupportedKeyHash,
+    /// The claim output amount is below the dust threshold
+    /// of the user descriptor
+    DustClaimOutput {
+        /// The amount of the claim output
+        amount: u64,
+        /// The dust threshold of the user descriptor
+        threshold: u64,
+    },
+}
+
+impl fmt::Display for PeginError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PeginError::UnspendableUserDescriptor => {
+                write!(f, "User descriptor cannot receive claimed funds")
+            }
+            PeginError::InsaneUserDescriptor => {
+                write!(f, "User descriptor sanity check failed")
+            }
+            PeginError::UnsupportedKeyHash => {
+                write!(
+                    f,
+                    "Key hashes are not supported in pegin federation scripts"
+                )
+            }
+            PeginError::DustClaimOutput { amount, threshold } => write!(
+                f,
+                "Claim output of {} is below the dust threshold {}",
+                amount, threshold
+            ),
+        }
+    }
+}
+
+impl error::Error for PeginError {}
+
+#[doc(hidden)]
+impl From<PeginError> for Error {
+    fn from(e: PeginError) -> Error {
+        Error::PeginError(e)
+    }
+}
@@ -22,6 +22,7 @@
 //! Thus, as a simple solution we implement these as a separate
 //! struct with it's own API.
 
+use bitcoin::consensus::encode::{Decodable, Encodable, VarInt};
 use bitcoin::hashes::Hash;
 use bitcoin::Script as BtcScript;
 use bitcoin::{self, blockdata::script, hashes};
@@ -29,11 +30,14 @@ use bitcoin::{self, blockdata::script, hashes};
 use bitcoin::{blockdata::opcodes, util::contracthash};
 use bitcoin::{hashes::hash160, Address as BtcAddress};
 use elements::secp256k1_zkp;
+use elements::{self, confidential, pset};
 use expression::{self, FromTree};
 use policy::{semantic, Liftable};
 use std::{
+    cmp,
     fmt::Debug,
     fmt::{self, Display},
+    hash, io,
     marker::PhantomData,
     str::FromStr,
     sync::Arc,
@@ -46,13 +50,21 @@ use {
     BtcPolicy, BtcSatisfier, BtcSegwitv0, BtcTerminal, BtcTree,
 };
 
-use {DescriptorTrait, Segwitv0, TranslatePk};
+use {DescriptorTrait, DummyKey, DummyKeyHash, Segwitv0, TranslatePk};
 
-use {tweak_key, util::varint_len};
+use {
+    tweak_key,
+    util::{varint_len, witness_size},
+};
 
-use descriptor::checksum::{desc_checksum, verify_checksum};
+use descriptor::checksum::{verify_checksum, write_with_checksum};
+use descriptor::diff::{added_and_removed, moved};
+use descriptor::{
+    ConfidentialDescriptor, ConfidentialKey, DescriptorDiff, DescriptorKeyParseError,
+    DescriptorPublicKey, DescriptorSecretKey, DescriptorType, DUST_RELAY_TX_FEE,
+};
 
-use super::PeginTrait;
+use super::{pegin_tweak_from_user_descriptor, OptimizeFor, PeginError, PeginTrait};
 use {MiniscriptKey, ToPublicKey};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -80,19 +92,27 @@ impl FromStr for LegacyPeginKey {
     // only allow compressed keys in LegacyPegin
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.is_empty() {
-            Err(Error::BadDescriptor(format!("Empty Legacy pegin")))
-        } else if &s[0..1] == "f" && s.len() == 67 {
-            Ok(LegacyPeginKey::Functionary(bitcoin::PublicKey::from_str(
-                &s[1..],
-            )?))
-        } else if &s[0..1] == "u" && s.len() == 67 {
-            Ok(LegacyPeginKey::NonFunctionary(
-                bitcoin::PublicKey::from_str(&s[1..])?,
-            ))
+        let parse_key = |key_str: &str| -> Result<bitcoin::PublicKey, Error> {
+            let pk = bitcoin::PublicKey::from_str(key_str).map_err(|e| {
+                Error::BadDescriptor(format!("Invalid Legacy Pegin key '{}': {}", s, e))
+            })?;
+            if pk.compressed {
+                Ok(pk)
+            } else {
+                Err(Error::BadDescriptor(format!(
+                    "Uncompressed Legacy Pegin key '{}'",
+                    s
+                )))
+            }
+        };
+        if let Some(key_str) = s.strip_prefix('f') {
+            Ok(LegacyPeginKey::Functionary(parse_key(key_str)?))
+        } else if let Some(key_str) = s.strip_prefix('u') {
+            Ok(LegacyPeginKey::NonFunctionary(parse_key(key_str)?))
         } else {
             Err(Error::BadDescriptor(format!(
-                "Invalid Legacy Pegin descriptor"
+                "Invalid Legacy Pegin key '{}': must start with 'f' or 'u'",
+                s
             )))
         }
     }
@@ -127,6 +147,31 @@ impl MiniscriptKey for LegacyPeginKey {
     }
 }
 
+/// An element of a [LegacyPegin] bitcoin witness template
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub enum WitnessItem {
+    /// A signature by the key. Federation keys sign with their
+    /// tweaked version.
+    Signature(LegacyPeginKey),
+    /// The extra value consumed by OP_CHECKMULTISIG
+    Dummy,
+    /// The witness script shared by both branches
+    Script,
+}
+
+/// The logical fields of a [LegacyPegin], as returned by
+/// [LegacyPegin::into_parts]: federation keys and threshold, emergency
+/// keys and threshold, timelock, user descriptor and blinding key
+pub type LegacyPeginParts<Pk> = (
+    Vec<LegacyPeginKey>,
+    usize,
+    Vec<LegacyPeginKey>,
+    usize,
+    u32,
+    Descriptor<Pk>,
+    Option<ConfidentialKey<Pk>>,
+);
+
 /// Legacy Pegin Descriptor
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub struct LegacyPegin<Pk: MiniscriptKey> {
@@ -142,6 +187,9 @@ pub struct LegacyPegin<Pk: MiniscriptKey> {
     pub timelock: u32,
     /// The elements descriptor required to redeem
     pub desc: Descriptor<Pk>,
+    /// The blinding key of the user descriptor, if the claim
+    /// output is confidential. It does not affect the tweak.
+    pub blinding_key: Option<ConfidentialKey<Pk>>,
     // Representation of federation policy as a miniscript
     // Allows for easier implementation
     ms: BtcMiniscript<LegacyPeginKey, BtcSegwitv0>,
@@ -176,10 +224,46 @@ impl<Pk: MiniscriptKey> LegacyPegin<Pk> {
             emer_k,
             timelock,
             desc,
+            blinding_key: None,
             ms,
         }
     }
 
+    /// Decomposes the pegin into the federation keys and threshold, the
+    /// emergency keys and threshold, the emergency timelock, the user
+    /// descriptor and its blinding key. The federation miniscript is
+    /// derived from these and is not returned.
+    pub fn into_parts(self) -> LegacyPeginParts<Pk> {
+        (
+            self.fed_pks,
+            self.fed_k,
+            self.emer_pks,
+            self.emer_k,
+            self.timelock,
+            self.desc,
+            self.blinding_key,
+        )
+    }
+
+    /// Rebuilds a pegin from the parts returned by
+    /// [LegacyPegin::into_parts], recomputing the federation miniscript.
+    /// Errors if either threshold is zero or exceeds its number of keys.
+    pub fn from_parts(
+        fed_pks: Vec<LegacyPeginKey>,
+        fed_k: usize,
+        emer_pks: Vec<LegacyPeginKey>,
+        emer_k: usize,
+        timelock: u32,
+        desc: Descriptor<Pk>,
+        blinding_key: Option<ConfidentialKey<Pk>>,
+    ) -> Result<Self, Error> {
+        check_threshold("federation", fed_k, fed_pks.len())?;
+        check_threshold("emergency", emer_k, emer_pks.len())?;
+        let mut pegin = Self::new(fed_pks, fed_k, emer_pks, emer_k, timelock, desc);
+        pegin.blinding_key = blinding_key;
+        Ok(pegin)
+    }
+
     // Internal function to set the fields of Self according to
     // miniscript
     fn from_ms_and_desc(
@@ -220,6 +304,7 @@ impl<Pk: MiniscriptKey> LegacyPegin<Pk> {
             emer_k,
             timelock,
             desc,
+            blinding_key: None,
             ms,
         }
     }
@@ -247,7 +332,7 @@ impl<Pk: MiniscriptKey> LegacyPegin<Pk> {
                     03ed1e8d5109c9ed66f7941bc53cc71137baa76d50d274bda8d5e8ffbd6e61fe9a";
         let fed_pks: Vec<LegacyPeginKey> = pks
             .split(",")
-            .map(|pk| LegacyPeginKey::Functionary(bitcoin::PublicKey::from_str(pk).unwrap()))
+            .map(|pk| LegacyPeginKey::Functionary(bitcoin::PublicKey::from_str(pk.trim()).unwrap()))
             .collect();
 
         let emer_pks = "
@@ -256,24 +341,685 @@ impl<Pk: MiniscriptKey> LegacyPegin<Pk> {
                     0386aa9372fbab374593466bc5451dc59954e90787f08060964d95c87ef34ca5bb";
         let emer_pks: Vec<LegacyPeginKey> = emer_pks
             .split(",")
-            .map(|pk| LegacyPeginKey::Functionary(bitcoin::PublicKey::from_str(pk).unwrap()))
+            .map(|pk| LegacyPeginKey::Functionary(bitcoin::PublicKey::from_str(pk.trim()).unwrap()))
             .collect();
 
         Self::new(fed_pks, 11, emer_pks, 2, 4032, user_desc)
     }
+
+    // Internal function to serialize the bitcoin witness script with the
+    // given (possibly tweaked) federation keys
+    fn witness_script_with_fed_pks(
+        &self,
+        fed_pks: &[bitcoin::PublicKey],
+    ) -> Result<BtcScript, Error> {
+        // Hopefully, we never have to use this and dynafed is deployed
+        let mut builder = script::Builder::new()
+            .push_opcode(opcodes::all::OP_DEPTH)
+            .push_int(self.fed_k as i64 + 1)
+            .push_opcode(opcodes::all::OP_EQUAL)
+            .push_opcode(opcodes::all::OP_IF)
+            // manually serialize the left CMS branch, without the OP_CMS
+            .push_int(self.fed_k as i64);
+
+        for key in fed_pks {
+            builder = builder.push_key(key);
+        }
+        let mut nearly_done = builder
+            .push_int(self.fed_pks.len() as i64)
+            .push_opcode(opcodes::all::OP_ELSE)
+            .into_script()
+            .to_bytes();
+
+        let right = if let BtcTerminal::OrD(_l, right) = &self.ms.node {
+            right
+        } else {
+            unreachable!("Only valid pegin descriptors should be created inside LegacyPegin")
+        };
+        let right = right.translate_pk(
+            |pk| Ok(*pk.as_untweaked()),
+            |_| Err(PeginError::UnsupportedKeyHash),
+        )?;
+        let mut rser = right.encode().into_bytes();
+        // ...and we have an OP_VERIFY style checksequenceverify, which in
+        // Liquid production was encoded with OP_DROP instead...
+        assert_eq!(rser[4], opcodes::all::OP_VERIFY.into_u8());
+        rser[4] = opcodes::all::OP_DROP.into_u8();
+        // ...then we should serialize it by sharing the OP_CMS across
+        // both branches, and add an OP_DEPTH check to distinguish the
+        // branches rather than doing the normal cascade construction
+        nearly_done.extend(rser);
+
+        let insert_point = nearly_done.len() - 1;
+        nearly_done.insert(insert_point, 0x68);
+        Ok(bitcoin::Script::from(nearly_done))
+    }
+
+    // The size of the bitcoin witness script. Tweaking keys does not
+    // change their serialized length, so we can use the untweaked keys.
+    fn witness_script_size(&self) -> Result<usize, Error> {
+        let fed_pks: Vec<bitcoin::PublicKey> =
+            self.fed_pks.iter().map(|key| *key.as_untweaked()).collect();
+        Ok(self.witness_script_with_fed_pks(&fed_pks)?.len())
+    }
+
+    /// Replaces the emergency keys, threshold and timelock, leaving the
+    /// federation branch and the user descriptor untouched.
+    pub fn with_emergency(
+        &self,
+        emer_pks: Vec<LegacyPeginKey>,
+        emer_k: usize,
+        timelock: u32,
+    ) -> Result<LegacyPegin<Pk>, Error> {
+        check_threshold("emergency", emer_k, emer_pks.len())?;
+        let fed_ms = if let BtcTerminal::OrD(ref fed_ms, _) = self.ms.node {
+            Arc::clone(fed_ms)
+        } else {
+            unreachable!("Only valid pegin descriptors should be created inside LegacyPegin")
+        };
+        let csv = BtcMiniscript::from_ast(BtcTerminal::Verify(Arc::new(
+            BtcMiniscript::from_ast(BtcTerminal::Older(timelock)).map_err(Error::BtcError)?,
+        )))
+        .map_err(Error::BtcError)?;
+        let emer_ms = BtcMiniscript::from_ast(BtcTerminal::Multi(emer_k, emer_pks.clone()))
+            .map_err(Error::BtcError)?;
+        let emer_ms = BtcMiniscript::from_ast(BtcTerminal::AndV(Arc::new(csv), Arc::new(emer_ms)))
+            .map_err(Error::BtcError)?;
+        let ms = BtcMiniscript::from_ast(BtcTerminal::OrD(fed_ms, Arc::new(emer_ms)))
+            .map_err(Error::BtcError)?;
+        Ok(LegacyPegin {
+            emer_pks,
+            emer_k,
+            timelock,
+            ms,
+            ..self.clone()
+        })
+    }
+
+    /// The number of dummy elements a witness needs for the extra value
+    /// popped by OP_CHECKMULTISIG. Both branches only push their keys and
+    /// thresholds and share a single OP_CHECKMULTISIG at the end of the
+    /// script, so the witness of either branch needs exactly one. It is
+    /// popped after the signatures, so it is the first witness element,
+    /// and must be empty to be standard (NULLDUMMY).
+    pub fn cms_extra_elements(&self) -> usize {
+        1
+    }
+
+    /// The witness stacks spending the federation and the emergency
+    /// branch, in that order. Each starts with the CHECKMULTISIG dummy,
+    /// followed by signatures of the first `k` keys of its branch, in key
+    /// order, and the script.
+    pub fn witness_templates(&self) -> (Vec<WitnessItem>, Vec<WitnessItem>) {
+        let template = |pks: &[LegacyPeginKey], k: usize| {
+            let mut items = vec![WitnessItem::Dummy; self.cms_extra_elements()];
+            items.extend(pks[..k].iter().map(|pk| WitnessItem::Signature(pk.clone())));
+            items.push(WitnessItem::Script);
+            items
+        };
+        (
+            template(&self.fed_pks, self.fed_k),
+            template(&self.emer_pks, self.emer_k),
+        )
+    }
+
+    /// The tweak applied to the federation keys, computed from the
+    /// user descriptor. See [pegin_tweak_from_user_descriptor].
+    pub fn tweak(&self) -> hashes::sha256::Hash
+    where
+        Pk: ToPublicKey + FromStr,
+        Pk::Hash: FromStr,
+        <Pk as FromStr>::Err: ToString,
+        <<Pk as MiniscriptKey>::Hash as FromStr>::Err: ToString,
+    {
+        pegin_tweak_from_user_descriptor(&self.desc)
+    }
+
+    /// The claim script of the peg-in, which the claim transaction on
+    /// the elements chain must reveal and the functionaries check the
+    /// deposit against. This is the scriptPubKey of the user descriptor.
+    pub fn claim_script(&self) -> elements::Script
+    where
+        Pk: ToPublicKey + FromStr,
+        Pk::Hash: FromStr,
+        <Pk as FromStr>::Err: ToString,
+        <<Pk as MiniscriptKey>::Hash as FromStr>::Err: ToString,
+    {
+        self.desc.script_pubkey()
+    }
+
+    /// The weight of an input spending the deposit on the bitcoin chain
+    /// through the branch with threshold `k`: the scriptSig pushing the
+    /// p2wsh program, the `k` signatures (assumed 73 bytes each), the
+    /// CHECKMULTISIG dummy and the witness script.
+    fn branch_satisfaction_weight(&self, k: usize) -> Result<usize, Error> {
+        let script_size = self.witness_script_size()?;
+        let n_elements = k + self.cms_extra_elements() + 1;
+        Ok(4 * 36
+            + varint_len(n_elements)
+            + 73 * k
+            + self.cms_extra_elements()
+            + varint_len(script_size)
+            + script_size)
+    }
+
+    /// Estimates the fees of a peg-in round-trip: the federation spending
+    /// the deposit on the bitcoin chain at `btc_feerate`, and the user
+    /// spending the claimed funds on the elements chain at
+    /// `elements_feerate`, both in satoshis per 1000 virtual bytes.
+    ///
+    /// The bitcoin leg is costed through the federation branch, the one
+    /// used by standard spends, even when the emergency branch is smaller.
+    pub fn estimate_pegin_cost(
+        &self,
+        btc_feerate: u64,
+        elements_feerate: u64,
+    ) -> Result<PeginCost, Error>
+    where
+        Pk: ToPublicKey + FromStr,
+        Pk::Hash: FromStr,
+        <Pk as FromStr>::Err: ToString,
+        <<Pk as MiniscriptKey>::Hash as FromStr>::Err: ToString,
+    {
+        let btc_weight = self.branch_satisfaction_weight(self.fed_k)?;
+        let elements_weight = self
+            .elements_spend_weight()
+            .ok_or(PeginError::UnspendableUserDescriptor)?;
+        // 1000 virtual bytes are 4000 weight units, rounding up
+        let fee = |weight: usize, feerate: u64| (weight as u64 * feerate + 3999) / 4000;
+        let btc_claim_fee = fee(btc_weight, btc_feerate);
+        let elements_spend_fee = fee(elements_weight, elements_feerate);
+        Ok(PeginCost {
+            btc_claim_fee,
+            elements_spend_fee,
+            total: btc_claim_fee + elements_spend_fee,
+        })
+    }
+
+    /// Builds an unsigned PSET claiming the peg-in deposited at
+    /// `deposit_outpoint` on the bitcoin chain whose genesis block is
+    /// `genesis_hash`. The claim pays `deposit_amount - fee` of `asset`
+    /// to the user descriptor in a single output, which is marked for
+    /// blinding if the user descriptor is confidential.
+    ///
+    /// The claim script is [LegacyPegin::claim_script]. The bitcoin
+    /// transaction and its merkle proof must be filled in before the
+    /// input can be finalized.
+    pub fn build_claim_pset<C: secp256k1_zkp::Signing>(
+        &self,
+        deposit_outpoint: bitcoin::OutPoint,
+        deposit_amount: u64,
+        genesis_hash: bitcoin::BlockHash,
+        asset: elements::AssetId,
+        fee: u64,
+        secp: &secp256k1_zkp::Secp256k1<C>,
+    ) -> Result<pset::PartiallySignedTransaction, Error>
+    where
+        Pk: ToPublicKey + FromStr,
+        Pk::Hash: FromStr,
+        <Pk as FromStr>::Err: ToString,
+        <<Pk as MiniscriptKey>::Hash as FromStr>::Err: ToString,
+    {
+        if fee > deposit_amount {
+            return Err(Error::Unexpected(format!(
+                "fee {} exceeds the pegin amount {}",
+                fee, deposit_amount
+            )));
+        }
+        let mut input = pset::Input::from_prevout(elements::OutPoint {
+            txid: elements::Txid::from_inner(deposit_outpoint.txid.into_inner()),
+            vout: deposit_outpoint.vout,
+        });
+        // Mark the input as a pegin, as in `pset::Input::from_txin`
+        input.previous_output_index |= 1 << 30;
+        input.pegin_value = Some(deposit_amount);
+        input.pegin_genesis_hash = Some(elements::BlockHash::from_inner(genesis_hash.into_inner()));
+        input.pegin_claim_script = Some(self.claim_script());
+        input.pegin_tx = None;
+        input.pegin_txout_proof = None;
+
+        let amount = deposit_amount - fee;
+        let ct_desc = self.confidential_user_descriptor();
+        let threshold = match ct_desc {
+            Some(ref ct) => ct.dust_threshold(DUST_RELAY_TX_FEE),
+            None => self.desc.dust_threshold(DUST_RELAY_TX_FEE),
+        };
+        if amount < threshold {
+            return Err(PeginError::DustClaimOutput { amount, threshold }.into());
+        }
+        let blinding_key = ct_desc.map(|ct| bitcoin::PublicKey {
+            compressed: true,
+            key: ct.blinding_pubkey(secp),
+        });
+        let output = pset::Output {
+            script_pubkey: self.desc.script_pubkey(),
+            amount: confidential::Value::Explicit(amount),
+            asset: confidential::Asset::Explicit(asset),
+            blinder_index: blinding_key.map(|_| 0),
+            blinding_key,
+            ..Default::default()
+        };
+
+        let mut pset = pset::PartiallySignedTransaction::new_v2();
+        pset.add_input(input);
+        pset.add_output(output);
+        Ok(pset)
+    }
+
+    /// Reports the federation and emergency keys added, removed and moved
+    /// in `other` compared to `self`, and the difference between the user
+    /// descriptors and their blinding keys.
+    pub fn diff(&self, other: &Self) -> LegacyPeginDiff<Pk> {
+        let (added_fed_pks, removed_fed_pks) = added_and_removed(&self.fed_pks, &other.fed_pks);
+        let (added_emer_pks, removed_emer_pks) = added_and_removed(&self.emer_pks, &other.emer_pks);
+        LegacyPeginDiff {
+            added_fed_pks,
+            removed_fed_pks,
+            moved_fed_pks: moved(&self.fed_pks, &other.fed_pks),
+            added_emer_pks,
+            removed_emer_pks,
+            moved_emer_pks: moved(&self.emer_pks, &other.emer_pks),
+            thresholds_changed: self.fed_k != other.fed_k || self.emer_k != other.emer_k,
+            timelock_changed: self.timelock != other.timelock,
+            user_desc: self.desc.diff(&other.desc),
+            blinding_key_changed: self.blinding_key != other.blinding_key,
+        }
+    }
+
+    /// Like [PeginTrait::get_bitcoin_satisfaction], but `optimize` decides
+    /// the branch when both the federation and the (timelocked) emergency
+    /// branch can be satisfied. [OptimizeFor::Standardness] always uses the
+    /// federation branch, [OptimizeFor::Fee] the one with the smaller witness.
+    pub fn get_bitcoin_satisfaction_for<S, C: secp256k1_zkp::Verification>(
+        &self,
+        secp: &secp256k1_zkp::Secp256k1<C>,
+        satisfier: S,
+        optimize: OptimizeFor,
+    ) -> Result<(Vec<Vec<u8>>, BtcScript), Error>
+    where
+        S: BtcSatisfier<bitcoin::PublicKey>,
+        Pk: ToPublicKey + FromStr,
+        Pk::Hash: FromStr,
+        <Pk as FromStr>::Err: ToString,
+        <<Pk as MiniscriptKey>::Hash as FromStr>::Err: ToString,
+    {
+        let tweak = self.tweak();
+        let unsigned_script_sig = self.bitcoin_unsigned_script_sig(secp)?;
+        let tweaked_fed_pks: Vec<bitcoin::PublicKey> = self
+            .fed_pks
+            .iter()
+            .map(|key| tweak_key(key.as_untweaked(), secp, tweak.as_inner()))
+            .collect();
+        let emer_pks: Vec<bitcoin::PublicKey> = self
+            .emer_pks
+            .iter()
+            .map(|key| *key.as_untweaked())
+            .collect();
+
+        let fed_wit = multi_witness(&satisfier, &tweaked_fed_pks, self.fed_k);
+        // The emergency branch is only spendable once its csv has matured
+        let emer_wit = if satisfier.check_older(self.timelock) {
+            multi_witness(&satisfier, &emer_pks, self.emer_k)
+        } else {
+            None
+        };
+        let sigs = match (fed_wit, emer_wit) {
+            (Some(fed), Some(emer))
+                if optimize == OptimizeFor::Fee && witness_size(&emer) < witness_size(&fed) =>
+            {
+                emer
+            }
+            // Prefer using federation keys over emergency paths
+            (Some(fed), _) => fed,
+            (None, Some(emer)) => emer,
+            (None, None) => return Err(Error::CouldNotSatisfy),
+        };
+        // The dummy sits below the signatures, and must be empty (NULLDUMMY)
+        let mut wit = vec![vec![]; self.cms_extra_elements()];
+        wit.extend(sigs);
+        wit.push(self.bitcoin_witness_script(secp)?.into_bytes());
+        Ok((wit, unsigned_script_sig))
+    }
+
+    /// Serializes the pegin into a versioned binary encoding: a version
+    /// byte, the federation keys and threshold, the emergency keys and
+    /// threshold, the csv timelock and the user descriptor string.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![LEGACY_PEGIN_ENCODING_VERSION];
+        encode_keys(&mut bytes, &self.fed_pks, self.fed_k);
+        encode_keys(&mut bytes, &self.emer_pks, self.emer_k);
+        let user_desc = match self.confidential_user_descriptor() {
+            Some(ct) => ct.to_string(),
+            None => self.desc.to_string(),
+        };
+        self.timelock
+            .consensus_encode(&mut bytes)
+            .expect("writing to a vec cannot fail");
+        user_desc
+            .consensus_encode(&mut bytes)
+            .expect("writing to a vec cannot fail");
+        bytes
+    }
+
+    /// Deserializes a pegin encoded with [LegacyPegin::to_bytes]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error>
+    where
+        Pk: FromStr,
+        Pk::Hash: FromStr,
+        <Pk as FromStr>::Err: ToString,
+        <<Pk as MiniscriptKey>::Hash as FromStr>::Err: ToString,
+    {
+        let mut cursor = io::Cursor::new(bytes);
+        let version: u8 = Decodable::consensus_decode(&mut cursor).map_err(decode_error)?;
+        if version != LEGACY_PEGIN_ENCODING_VERSION {
+            return Err(Error::BadDescriptor(format!(
+                "Unknown legacy pegin encoding version {}",
+                version
+            )));
+        }
+        let (fed_pks, fed_k) = decode_keys(&mut cursor)?;
+        let (emer_pks, emer_k) = decode_keys(&mut cursor)?;
+        let timelock: u32 = Decodable::consensus_decode(&mut cursor).map_err(decode_error)?;
+        let user_desc: String = Decodable::consensus_decode(&mut cursor).map_err(decode_error)?;
+        if cursor.position() != bytes.len() as u64 {
+            return Err(Error::BadDescriptor(
+                "Trailing bytes after legacy pegin encoding".to_owned(),
+            ));
+        }
+        if timelock == 0 || timelock >= 1 << 31 {
+            return Err(Error::BadDescriptor(format!(
+                "Invalid legacy pegin timelock {}",
+                timelock
+            )));
+        }
+
+        let (desc, blinding_key) = if user_desc.starts_with("ct(") {
+            let ct = ConfidentialDescriptor::<Pk>::from_str(&user_desc)?;
+            (ct.descriptor, Some(ct.key))
+        } else {
+            (Descriptor::<Pk>::from_str(&user_desc)?, None)
+        };
+        let mut pegin = LegacyPegin::new(fed_pks, fed_k, emer_pks, emer_k, timelock, desc);
+        pegin.blinding_key = blinding_key;
+        Ok(pegin)
+    }
+
+    /// The confidential user descriptor, if a blinding key is set
+    pub fn confidential_user_descriptor(&self) -> Option<ConfidentialDescriptor<Pk>> {
+        self.blinding_key
+            .clone()
+            .map(|key| ConfidentialDescriptor::new(key, self.desc.clone()))
+    }
+}
+
+impl LegacyPegin<String> {
+    /// Converts every secret key in the user descriptor and its blinding
+    /// key to the corresponding public key, giving a watch-only pegin.
+    /// The functionary keys are always public and are left unchanged.
+    pub fn to_public<C: secp256k1_zkp::Signing>(
+        &self,
+        secp: &secp256k1_zkp::Secp256k1<C>,
+    ) -> Result<LegacyPegin<DescriptorPublicKey>, Error> {
+        let to_public = |s: &String| -> Result<DescriptorPublicKey, DescriptorKeyParseError> {
+            match DescriptorSecretKey::from_str(s) {
+                Ok(sk) => sk.as_public(secp),
+                Err(_) => DescriptorPublicKey::from_str(s),
+            }
+        };
+        let desc = self
+            .desc
+            .translate_pk(&to_public, &to_public)
+            .map_err(|e| Error::Unexpected(e.to_string()))?;
+        let blinding_key = match self.blinding_key {
+            Some(ConfidentialKey::Slip77(sk)) => Some(ConfidentialKey::Slip77(sk)),
+            Some(ConfidentialKey::Bare(ref pk)) => Some(ConfidentialKey::Bare(
+                to_public(pk).map_err(|e| Error::Unexpected(e.to_string()))?,
+            )),
+            None => None,
+        };
+        Ok(LegacyPegin {
+            fed_pks: self.fed_pks.clone(),
+            fed_k: self.fed_k,
+            emer_pks: self.emer_pks.clone(),
+            emer_k: self.emer_k,
+            timelock: self.timelock,
+            desc,
+            blinding_key,
+            ms: self.ms.clone(),
+        })
+    }
+}
+
+/// Version byte of the [LegacyPegin::to_bytes] encoding
+const LEGACY_PEGIN_ENCODING_VERSION: u8 = 1;
+
+// Writes a threshold of keys as the number of keys, each key as a type
+// byte followed by its 33 bytes, and the threshold
+fn encode_keys(bytes: &mut Vec<u8>, pks: &[LegacyPeginKey], k: usize) {
+    VarInt(pks.len() as u64)
+        .consensus_encode(&mut *bytes)
+        .expect("writing to a vec cannot fail");
+    for pk in pks {
+        match *pk {
+            LegacyPeginKey::Functionary(ref pk) => {
+                bytes.push(0);
+                bytes.extend(&pk.to_bytes());
+            }
+            LegacyPeginKey::NonFunctionary(ref pk) => {
+                bytes.push(1);
+                bytes.extend(&pk.to_bytes());
+            }
+        }
+    }
+    VarInt(k as u64)
+        .consensus_encode(&mut *bytes)
+        .expect("writing to a vec cannot fail");
+}
+
+fn decode_keys(cursor: &mut io::Cursor<&[u8]>) -> Result<(Vec<LegacyPeginKey>, usize), Error> {
+    let n = VarInt::consensus_decode(&mut *cursor)
+        .map_err(decode_error)?
+        .0;
+    // CHECKMULTISIG allows at most 20 keys
+    if n > 20 {
+        return Err(Error::BadDescriptor(format!(
+            "Too many legacy pegin keys: {}",
+            n
+        )));
+    }
+    let mut pks = Vec::with_capacity(n as usize);
+    for _ in 0..n {
+        let flag = u8::consensus_decode(&mut *cursor).map_err(decode_error)?;
+        let mut key = [0; 33];
+        io::Read::read_exact(&mut *cursor, &mut key)
+            .map_err(|e| decode_error(bitcoin::consensus::encode::Error::Io(e)))?;
+        let pk = bitcoin::PublicKey::from_slice(&key)
+            .map_err(|e| Error::BadDescriptor(format!("Invalid legacy pegin key: {}", e)))?;
+        match flag {
+            0 => pks.push(LegacyPeginKey::Functionary(pk)),
+            1 => pks.push(LegacyPeginKey::NonFunctionary(pk)),
+            _ => {
+                return Err(Error::BadDescriptor(format!(
+                    "Invalid legacy pegin key type {}",
+                    flag
+                )))
+            }
+        }
+    }
+    let k = VarInt::consensus_decode(&mut *cursor)
+        .map_err(decode_error)?
+        .0;
+    if k == 0 || k > n {
+        return Err(Error::BadDescriptor(format!(
+            "Invalid legacy pegin threshold {} of {}",
+            k, n
+        )));
+    }
+    Ok((pks, k as usize))
+}
+
+fn decode_error(e: bitcoin::consensus::encode::Error) -> Error {
+    Error::BadDescriptor(format!("Invalid legacy pegin encoding: {}", e))
+}
+
+// Collects the `k` shortest signatures for `pks`, if at least `k`
+// are available. OP_CHECKMULTISIG needs the signatures in the order
+// of their keys, so they are returned in that order.
+fn multi_witness<S: BtcSatisfier<bitcoin::PublicKey>>(
+    satisfier: &S,
+    pks: &[bitcoin::PublicKey],
+    k: usize,
+) -> Option<Vec<Vec<u8>>> {
+    let mut sigs: Vec<(usize, Vec<u8>)> = pks
+        .iter()
+        .enumerate()
+        .filter_map(|(i, pk)| satisfier.lookup_sig(pk).map(|sig| (i, sig)))
+        .map(|(i, sig)| {
+            let mut sig_vec = sig.0.serialize_der().to_vec();
+            sig_vec.push(sig.1.as_u32() as u8);
+            (i, sig_vec)
+        })
+        .collect();
+    if sigs.len() < k {
+        return None;
+    }
+    sigs.sort_by_key(|(_, sig)| sig.len());
+    sigs.truncate(k);
+    sigs.sort_by_key(|&(i, _)| i);
+    Some(sigs.into_iter().map(|(_, sig)| sig).collect())
+}
+
+// Checks that a k-of-n multisig threshold is satisfiable
+fn check_threshold(branch: &str, k: usize, n: usize) -> Result<(), Error> {
+    if k == 0 || k > n {
+        return Err(Error::BadDescriptor(format!(
+            "Invalid {} threshold {} of {} keys",
+            branch, k, n
+        )));
+    }
+    Ok(())
+}
+
+/// The difference between an old and a new [LegacyPegin]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct LegacyPeginDiff<Pk: MiniscriptKey> {
+    /// Federation keys present only in the new pegin
+    pub added_fed_pks: Vec<LegacyPeginKey>,
+    /// Federation keys present only in the old pegin
+    pub removed_fed_pks: Vec<LegacyPeginKey>,
+    /// Federation keys present in both pegins, but now at a position that
+    /// held a different key
+    pub moved_fed_pks: Vec<LegacyPeginKey>,
+    /// Emergency keys present only in the new pegin
+    pub added_emer_pks: Vec<LegacyPeginKey>,
+    /// Emergency keys present only in the old pegin
+    pub removed_emer_pks: Vec<LegacyPeginKey>,
+    /// Emergency keys present in both pegins, but now at a position that
+    /// held a different key
+    pub moved_emer_pks: Vec<LegacyPeginKey>,
+    /// Whether the federation or emergency threshold changed
+    pub thresholds_changed: bool,
+    /// Whether the emergency csv timelock changed
+    pub timelock_changed: bool,
+    /// The difference between the user descriptors
+    pub user_desc: DescriptorDiff<Pk>,
+    /// Whether the blinding key of the user descriptor changed
+    pub blinding_key_changed: bool,
+}
+
+impl<Pk: MiniscriptKey> LegacyPeginDiff<Pk> {
+    /// Whether the two pegins are identical
+    pub fn is_empty(&self) -> bool {
+        self.added_fed_pks.is_empty()
+            && self.removed_fed_pks.is_empty()
+            && self.moved_fed_pks.is_empty()
+            && self.added_emer_pks.is_empty()
+            && self.removed_emer_pks.is_empty()
+            && self.moved_emer_pks.is_empty()
+            && !self.thresholds_changed
+            && !self.timelock_changed
+            && self.user_desc.is_empty()
+            && !self.blinding_key_changed
+    }
+}
+
+/// The estimated fees of a peg-in round-trip, in satoshis. See
+/// [LegacyPegin::estimate_pegin_cost].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PeginCost {
+    /// The fee for spending the deposit on the bitcoin chain
+    pub btc_claim_fee: u64,
+    /// The fee for spending the claimed funds on the elements chain
+    pub elements_spend_fee: u64,
+    /// The sum of both fees
+    pub total: u64,
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for LegacyPeginDiff<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("no changes");
+        }
+        let mut changes = vec![];
+        changes.extend(self.added_fed_pks.iter().map(|pk| format!("+fed {}", pk)));
+        changes.extend(self.removed_fed_pks.iter().map(|pk| format!("-fed {}", pk)));
+        changes.extend(self.moved_fed_pks.iter().map(|pk| format!("~fed {}", pk)));
+        changes.extend(self.added_emer_pks.iter().map(|pk| format!("+emer {}", pk)));
+        changes.extend(
+            self.removed_emer_pks
+                .iter()
+                .map(|pk| format!("-emer {}", pk)),
+        );
+        changes.extend(self.moved_emer_pks.iter().map(|pk| format!("~emer {}", pk)));
+        if self.thresholds_changed {
+            changes.push("thresholds changed".to_owned());
+        }
+        if self.timelock_changed {
+            changes.push("timelock changed".to_owned());
+        }
+        if !self.user_desc.is_empty() {
+            changes.push(format!("user descriptor: {}", self.user_desc));
+        }
+        if self.blinding_key_changed {
+            changes.push("blinding key changed".to_owned());
+        }
+        f.write_str(&changes.join(", "))
+    }
+}
+
+// The federation miniscript is determined by the other fields and the
+// user descriptor has no `Hash` impl, so hash its canonical string form.
+impl<Pk: MiniscriptKey> hash::Hash for LegacyPegin<Pk> {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.fed_pks.hash(state);
+        self.fed_k.hash(state);
+        self.emer_pks.hash(state);
+        self.emer_k.hash(state);
+        self.timelock.hash(state);
+        format!("{:#}", self.desc).hash(state);
+        self.blinding_key
+            .as_ref()
+            .map(|key| key.to_string())
+            .hash(state);
+    }
 }
 
 impl<Pk: MiniscriptKey> fmt::Debug for LegacyPegin<Pk> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "legacy_pegin({:?},{:?})", self.ms, self.desc)
+        match self.confidential_user_descriptor() {
+            Some(ct) => write!(f, "legacy_pegin({:?},{:?})", self.ms, ct),
+            None => write!(f, "legacy_pegin({:?},{:?})", self.ms, self.desc),
+        }
     }
 }
 
 impl<Pk: MiniscriptKey> fmt::Display for LegacyPegin<Pk> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let desc = format!("legacy_pegin({},{})", self.ms, self.desc);
-        let checksum = desc_checksum(&desc).map_err(|_| fmt::Error)?;
-        write!(f, "{}#{}", &desc, &checksum)
+        let desc = match self.confidential_user_descriptor() {
+            Some(ct) => format!("legacy_pegin({},{:#})", self.ms, ct),
+            None => format!("legacy_pegin({},{:#})", self.ms, self.desc),
+        };
+        write_with_checksum(f, &desc)
     }
 }
 
@@ -302,11 +1048,22 @@ where
             // a roundtrip hack to use FromTree from bitcoin::Miniscript from
             // expression::Tree in elements.
             let ms_str = top.args[0].to_string();
-            let ms_expr = BtcTree::from_str(&ms_str)?;
+            let ms_expr =
+                BtcTree::from_str(&ms_str).map_err(|e| expression::arg_error(top, 0, e))?;
             //
-            let ms = BtcMiniscript::<LegacyPeginKey, BtcSegwitv0>::from_tree(&ms_expr);
-            let desc = Descriptor::<Pk>::from_tree(&top.args[1]);
-            Ok(LegacyPegin::from_ms_and_desc(desc?, ms?))
+            let ms = BtcMiniscript::<LegacyPeginKey, BtcSegwitv0>::from_tree(&ms_expr)
+                .map_err(|e| expression::arg_error(top, 0, e));
+            if top.args[1].name == "ct" {
+                let ct = ConfidentialDescriptor::<Pk>::from_tree(&top.args[1])
+                    .map_err(|e| expression::arg_error(top, 1, e))?;
+                let mut pegin = LegacyPegin::from_ms_and_desc(ct.descriptor, ms?);
+                pegin.blinding_key = Some(ct.key);
+                Ok(pegin)
+            } else {
+                let desc = Descriptor::<Pk>::from_tree(&top.args[1])
+                    .map_err(|e| expression::arg_error(top, 1, e));
+                Ok(LegacyPegin::from_ms_and_desc(desc?, ms?))
+            }
         } else {
             Err(Error::Unexpected(format!(
                 "{}({} args) while parsing legacy_pegin descriptor",
@@ -346,7 +1103,13 @@ where
             .map_err(|_| Error::Unexpected(format!("Federation script sanity check failed")))?;
         self.desc
             .sanity_check()
-            .map_err(|_| Error::Unexpected(format!("Federation script sanity check failed")))?;
+            .map_err(|_| PeginError::InsaneUserDescriptor)?;
+        // The claimed funds must be paid to an address. Whether there is
+        // one does not depend on the keys, so check with dummy ones.
+        self.desc
+            .translate_pk_infallible(|_| DummyKey, |_| DummyKeyHash)
+            .address(&elements::AddressParams::ELEMENTS)
+            .map_err(|_| PeginError::UnspendableUserDescriptor)?;
         Ok(())
     }
 
@@ -359,7 +1122,7 @@ where
         Pk: ToPublicKey,
     {
         Ok(bitcoin::Address::p2shwsh(
-            &self.bitcoin_witness_script(secp),
+            &self.bitcoin_witness_script(secp)?,
             network,
         ))
     }
@@ -367,78 +1130,41 @@ where
     fn bitcoin_script_pubkey<C: secp256k1_zkp::Verification>(
         &self,
         secp: &secp256k1_zkp::Secp256k1<C>,
-    ) -> BtcScript
+    ) -> Result<BtcScript, Error>
     where
         Pk: ToPublicKey,
     {
-        self.bitcoin_address(bitcoin::Network::Bitcoin, secp)
-            .expect("Address cannot fail for pegin")
-            .script_pubkey()
+        // p2sh scriptpubkeys do not depend on the network
+        Ok(self.bitcoin_witness_script(secp)?.to_v0_p2wsh().to_p2sh())
     }
 
     fn bitcoin_unsigned_script_sig<C: secp256k1_zkp::Verification>(
         &self,
         secp: &secp256k1_zkp::Secp256k1<C>,
-    ) -> BtcScript
+    ) -> Result<BtcScript, Error>
     where
         Pk: ToPublicKey,
     {
-        let witness_script = self.bitcoin_witness_script(secp);
-        script::Builder::new()
+        let witness_script = self.bitcoin_witness_script(secp)?;
+        Ok(script::Builder::new()
             .push_slice(&witness_script.to_v0_p2wsh()[..])
-            .into_script()
+            .into_script())
     }
 
     fn bitcoin_witness_script<C: secp256k1_zkp::Verification>(
         &self,
         secp: &secp256k1_zkp::Secp256k1<C>,
-    ) -> BtcScript
+    ) -> Result<BtcScript, Error>
     where
         Pk: ToPublicKey,
     {
-        let tweak_vec = self.desc.explicit_script().into_bytes();
-        let tweak = hashes::sha256::Hash::hash(&tweak_vec);
-        // Hopefully, we never have to use this and dynafed is deployed
-        let mut builder = script::Builder::new()
-            .push_opcode(opcodes::all::OP_DEPTH)
-            .push_int(self.fed_k as i64 + 1)
-            .push_opcode(opcodes::all::OP_EQUAL)
-            .push_opcode(opcodes::all::OP_IF)
-            // manually serialize the left CMS branch, without the OP_CMS
-            .push_int(self.fed_k as i64);
-
-        for key in &self.fed_pks {
-            let tweaked_pk = tweak_key(key.as_untweaked(), secp, tweak.as_inner());
-            builder = builder.push_key(&tweaked_pk);
-        }
-        let mut nearly_done = builder
-            .push_int(self.fed_pks.len() as i64)
-            .push_opcode(opcodes::all::OP_ELSE)
-            .into_script()
-            .to_bytes();
-
-        let right = if let BtcTerminal::OrD(_l, right) = &self.ms.node {
-            right
-        } else {
-            unreachable!("Only valid pegin descriptors should be created inside LegacyPegin")
-        };
-        let right = right.translate_pk_infallible(
-            |pk| pk.as_untweaked().clone(),
-            |_| unreachable!("No Keyhashes in legacy pegins"),
-        );
-        let mut rser = right.encode().into_bytes();
-        // ...and we have an OP_VERIFY style checksequenceverify, which in
-        // Liquid production was encoded with OP_DROP instead...
-        assert_eq!(rser[4], opcodes::all::OP_VERIFY.into_u8());
-        rser[4] = opcodes::all::OP_DROP.into_u8();
-        // ...then we should serialize it by sharing the OP_CMS across
-        // both branches, and add an OP_DEPTH check to distinguish the
-        // branches rather than doing the normal cascade construction
-        nearly_done.extend(rser);
-
-        let insert_point = nearly_done.len() - 1;
-        nearly_done.insert(insert_point, 0x68);
-        bitcoin::Script::from(nearly_done)
+        let tweak = self.tweak();
+        let tweaked_fed_pks: Vec<bitcoin::PublicKey> = self
+            .fed_pks
+            .iter()
+            .map(|key| tweak_key(key.as_untweaked(), secp, tweak.as_inner()))
+            .collect();
+        self.witness_script_with_fed_pks(&tweaked_fed_pks)
     }
 
     fn get_bitcoin_satisfaction<S, C: secp256k1_zkp::Verification>(
@@ -450,70 +1176,882 @@ where
         S: BtcSatisfier<bitcoin::PublicKey>,
         Pk: ToPublicKey,
     {
-        let tweak_vec = self.desc.explicit_script().into_bytes();
-        let tweak = hashes::sha256::Hash::hash(&tweak_vec);
-        let unsigned_script_sig = self.bitcoin_unsigned_script_sig(secp);
-        let mut sigs = vec![];
-        for key in &self.fed_pks {
-            let tweaked_pk = tweak_key(key.as_untweaked(), secp, tweak.as_inner());
-            match satisfier.lookup_sig(&tweaked_pk) {
-                Some(sig) => {
-                    let mut sig_vec = sig.0.serialize_der().to_vec();
-                    sig_vec.push(sig.1.as_u32() as u8);
-                    sigs.push(sig_vec)
-                }
-                None => {}
-            }
-        }
-        sigs.sort_by(|a, b| a.len().cmp(&b.len()));
-        if sigs.len() >= self.fed_k {
-            // Prefer using federation keys over emergency paths
-            let mut sigs: Vec<Vec<u8>> = sigs.into_iter().take(self.fed_k).collect();
-            sigs.push(vec![0]); // CMS extra value
-            Ok((sigs, unsigned_script_sig))
-        } else {
-            let mut emer_sigs = vec![];
-            for emer_key in &self.emer_pks {
-                match satisfier.lookup_sig(emer_key.as_untweaked()) {
-                    Some(sig) => {
-                        let mut sig_vec = sig.0.serialize_der().to_vec();
-                        sig_vec.push(sig.1.as_u32() as u8);
-                        emer_sigs.push(sig_vec)
-                    }
-                    None => {}
-                }
-            }
-            emer_sigs.sort_by(|a, b| a.len().cmp(&b.len()));
-            if emer_sigs.len() >= self.emer_k {
-                let mut sigs: Vec<Vec<u8>> = emer_sigs.into_iter().take(self.emer_k).collect();
-                sigs.push(vec![0]); // CMS extra value
-                Ok((sigs, unsigned_script_sig))
-            } else {
-                Err(Error::CouldNotSatisfy)
-            }
-        }
+        self.get_bitcoin_satisfaction_for(secp, satisfier, OptimizeFor::Standardness)
     }
 
     fn max_satisfaction_weight(&self) -> Result<usize, Error> {
-        let script_size = 628;
-        Ok(4 * 36
-            + varint_len(script_size)
-            + script_size
-            + varint_len(self.ms.max_satisfaction_witness_elements()?)
-            + self.ms.max_satisfaction_size()?)
+        self.branch_satisfaction_weight(cmp::max(self.fed_k, self.emer_k))
+    }
+
+    fn elements_spend_weight(&self) -> Option<usize> {
+        self.desc.max_satisfaction_weight().ok()
     }
 
     fn script_code<C: secp256k1_zkp::Verification>(
         &self,
         secp: &secp256k1_zkp::Secp256k1<C>,
-    ) -> BtcScript
+    ) -> Result<BtcScript, Error>
     where
         Pk: ToPublicKey,
     {
         self.bitcoin_witness_script(secp)
     }
 
+    /// The explicit user descriptor. Its blinding key, if any, is
+    /// dropped; use [LegacyPegin::confidential_user_descriptor] to keep it.
     fn into_user_descriptor(self) -> Descriptor<Pk> {
         self.desc
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin_miniscript::miniscript::satisfy::Older as BtcOlder;
+    use descriptor::checksum::{desc_checksum, strip_checksum};
+    use std::collections::HashMap;
+
+    fn user_desc() -> Descriptor<bitcoin::PublicKey> {
+        Descriptor::from_str(
+            "elwpkh(020000000000000000000000000000000000000000000000000000000000000002)",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn parse_legacy_pegin_key() {
+        let pk = "020e0338c96a8870479f2396c373cc7696ba124e8635d41b0ea581112b67817261";
+        let key = LegacyPeginKey::from_str(&format!("f{}", pk)).unwrap();
+        assert_eq!(key.to_string(), format!("f{}", pk));
+        let key = LegacyPeginKey::from_str(&format!("u{}", pk)).unwrap();
+        assert_eq!(key.to_string(), format!("u{}", pk));
+
+        // Must not panic on multibyte characters or wrong lengths
+        for s in &[
+            "",
+            "f",
+            "é",
+            "é020e0338c96a8870479f2396c373cc7696ba124e8635d41b0ea581112b678172",
+            "fé0e0338c96a8870479f2396c373cc7696ba124e8635d41b0ea581112b67817261",
+            "f020e0338c96a8870479f2396c373cc7696ba124e8635d41b0ea581112b678172",
+            "f020e0338c96a8870479f2396c373cc7696ba124e8635d41b0ea581112b6781726100",
+            "x020e0338c96a8870479f2396c373cc7696ba124e8635d41b0ea581112b67817261",
+            "f0414fc03b8df87cd7b872996810db8458d61da8448e531569c8517b469a119d267be5645686309c6e6736dbd93940707cc9143d3cf29f1b877ff340e2cb2d259cf",
+        ] {
+            match LegacyPeginKey::from_str(s) {
+                Err(Error::BadDescriptor(..)) => {}
+                Err(e) => panic!("unexpected error {} for {}", e, s),
+                Ok(k) => panic!("{} must not parse, got {}", s, k),
+            }
+        }
+    }
+
+    #[test]
+    fn max_satisfaction_vsize() {
+        let pegin = LegacyPegin::new_legacy_fed(user_desc());
+        let secp = secp256k1_zkp::Secp256k1::verification_only();
+        assert_eq!(pegin.bitcoin_witness_script(&secp).unwrap().len(), 628);
+        assert_eq!(pegin.witness_script_size().unwrap(), 628);
+        // 4 * 36 scriptSig, 3 + 628 witness script and 1 + 1 + 803 for
+        // the element count, the empty CMS dummy and the 11 signatures
+        assert_eq!(pegin.max_satisfaction_weight().unwrap(), 1580);
+        assert_eq!(pegin.max_satisfaction_vsize().unwrap(), 395);
+    }
+
+    #[test]
+    fn tweak_from_user_descriptor() {
+        let pegin = LegacyPegin::new_legacy_fed(user_desc());
+        assert_eq!(
+            pegin_tweak_from_user_descriptor(&user_desc()),
+            pegin.tweak()
+        );
+    }
+
+    #[test]
+    fn estimate_pegin_cost() {
+        let pegin = LegacyPegin::new_legacy_fed(user_desc());
+        let cost = pegin.estimate_pegin_cost(10_000, 1_000).unwrap();
+        assert_eq!(cost.total, cost.btc_claim_fee + cost.elements_spend_fee);
+        assert_eq!(
+            cost.elements_spend_fee,
+            (pegin.elements_spend_weight().unwrap() as u64 + 3) / 4
+        );
+        // The 11-of-15 federation branch is the larger of the two, and
+        // weighs 1580 units, i.e. 3950 satoshis at 10 sat/vbyte
+        assert_eq!(pegin.max_satisfaction_weight().unwrap(), 1580);
+        assert_eq!(cost.btc_claim_fee, 3950);
+        // Fractional satoshis are rounded up
+        let cost = pegin.estimate_pegin_cost(10_001, 1_000).unwrap();
+        assert_eq!(cost.btc_claim_fee, 3951);
+
+        let free = pegin.estimate_pegin_cost(0, 0).unwrap();
+        assert_eq!(free.total, 0);
+    }
+
+    #[test]
+    fn elements_spend_weight() {
+        let pegin = LegacyPegin::new_legacy_fed(user_desc());
+        let standalone = user_desc();
+        assert_eq!(
+            pegin.elements_spend_weight(),
+            Some(standalone.max_satisfaction_weight().unwrap())
+        );
+    }
+
+    #[test]
+    fn bitcoin_script_pubkey_for_network() {
+        let pegin = LegacyPegin::new_legacy_fed(user_desc());
+        let secp = secp256k1_zkp::Secp256k1::verification_only();
+        let spk = pegin.bitcoin_script_pubkey(&secp).unwrap();
+        assert!(spk.is_p2sh());
+        for network in &[
+            bitcoin::Network::Bitcoin,
+            bitcoin::Network::Testnet,
+            bitcoin::Network::Regtest,
+        ] {
+            assert_eq!(
+                pegin
+                    .bitcoin_script_pubkey_for_network(*network, &secp)
+                    .unwrap(),
+                spk
+            );
+        }
+    }
+
+    #[test]
+    fn parse_default_pegin() {
+        let pegin = LegacyPegin::new_legacy_fed(user_desc());
+        let desc = pegin.desc.to_string();
+        let s = format!("legacy_pegin({},{})", pegin.ms, strip_checksum(&desc));
+        let parsed = LegacyPegin::<bitcoin::PublicKey>::from_str(&s).unwrap();
+        assert_eq!(parsed, pegin);
+
+        // The user descriptor is embedded without its checksum
+        assert_eq!(format!("{:#}", pegin), s);
+        assert_eq!(pegin.to_string().matches('#').count(), 1);
+        let parsed = LegacyPegin::<bitcoin::PublicKey>::from_str(&pegin.to_string()).unwrap();
+        assert_eq!(parsed, pegin);
+    }
+
+    #[test]
+    fn parse_error_context() {
+        let pegin = LegacyPegin::new_legacy_fed(user_desc());
+        let s = format!("legacy_pegin({},elsh(sortedmulti))", pegin.ms);
+        let err = LegacyPegin::<bitcoin::PublicKey>::from_str(&s).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "while parsing `legacy_pegin(...)` → arg 1 → while parsing `elsh(...)` → \
+             unexpected «no arguments given for sortedmulti»"
+        );
+        match *err.root() {
+            Error::Unexpected(_) => {}
+            ref e => panic!("unexpected root error {:?}", e),
+        }
+
+        let s = format!("legacy_pegin({},ct(elip151,elsh(sortedmulti)))", pegin.ms);
+        let err = LegacyPegin::<bitcoin::PublicKey>::from_str(&s).unwrap_err();
+        assert!(err.to_string().starts_with(
+            "while parsing `legacy_pegin(...)` → arg 1 → while parsing `ct(...)` → arg 0 → "
+        ));
+    }
+
+    #[test]
+    fn parse_confidential_user_descriptor() {
+        let pegin = LegacyPegin::new_legacy_fed(user_desc());
+        let slip77 = "slip77(b2396b3ee20509cdb64fe24b0dd20e0ac3ddb0dc3dd77d89fea7b6ac9d7e0dbe)";
+        let s = format!(
+            "legacy_pegin({},ct({},{:#}))",
+            pegin.ms,
+            slip77,
+            user_desc()
+        );
+        let ct_pegin = LegacyPegin::<bitcoin::PublicKey>::from_str(&s).unwrap();
+        assert_eq!(format!("{:#}", ct_pegin), s);
+        let parsed = LegacyPegin::<bitcoin::PublicKey>::from_str(&ct_pegin.to_string()).unwrap();
+        assert_eq!(parsed, ct_pegin);
+
+        match ct_pegin.blinding_key {
+            Some(ConfidentialKey::Slip77(..)) => {}
+            ref k => panic!("unexpected blinding key {:?}", k),
+        }
+        // The tweak only commits to the explicit user descriptor
+        assert_eq!(ct_pegin.tweak(), pegin.tweak());
+        let secp = secp256k1_zkp::Secp256k1::verification_only();
+        assert_eq!(
+            ct_pegin.bitcoin_script_pubkey(&secp).unwrap(),
+            pegin.bitcoin_script_pubkey(&secp).unwrap()
+        );
+        assert_eq!(ct_pegin.into_user_descriptor(), user_desc());
+    }
+
+    #[test]
+    fn build_claim_pset() {
+        let pegin = LegacyPegin::new_legacy_fed(user_desc());
+        let secp = secp256k1_zkp::Secp256k1::signing_only();
+        let outpoint = bitcoin::OutPoint::new(
+            bitcoin::Txid::from_str(
+                "0101010101010101010101010101010101010101010101010101010101010101",
+            )
+            .unwrap(),
+            1,
+        );
+        let genesis_hash =
+            bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Bitcoin).block_hash();
+        let asset = elements::AssetId::from_slice(&[7; 32]).unwrap();
+
+        let pset = pegin
+            .build_claim_pset(outpoint, 100_000, genesis_hash, asset, 1_000, &secp)
+            .unwrap();
+        assert_eq!(pset.inputs.len(), 1);
+        assert_eq!(pset.outputs.len(), 1);
+        let input = &pset.inputs[0];
+        assert!(input.is_pegin());
+        assert_eq!(input.pegin_value, Some(100_000));
+        assert_eq!(input.pegin_claim_script, Some(user_desc().script_pubkey()));
+        assert_eq!(
+            input.pegin_genesis_hash.unwrap().into_inner(),
+            genesis_hash.into_inner()
+        );
+        assert!(input.partial_sigs.is_empty());
+        assert!(input.final_script_witness.is_none());
+        let output = &pset.outputs[0];
+        assert_eq!(output.script_pubkey, user_desc().script_pubkey());
+        assert_eq!(output.amount, confidential::Value::Explicit(99_000));
+        assert_eq!(output.asset, confidential::Asset::Explicit(asset));
+        assert!(output.blinding_key.is_none());
+
+        assert!(pegin
+            .build_claim_pset(outpoint, 1_000, genesis_hash, asset, 1_001, &secp)
+            .is_err());
+
+        // Dust claim outputs are rejected, with a higher threshold
+        // for confidential outputs
+        assert!(pegin
+            .build_claim_pset(outpoint, 1_399, genesis_hash, asset, 1_000, &secp)
+            .is_ok());
+        match pegin.build_claim_pset(outpoint, 1_398, genesis_hash, asset, 1_000, &secp) {
+            Err(Error::PeginError(PeginError::DustClaimOutput { amount, threshold })) => {
+                assert_eq!((amount, threshold), (398, 399))
+            }
+            res => panic!("unexpected result {:?}", res),
+        }
+        let mut ct_pegin = pegin.clone();
+        ct_pegin.blinding_key = Some(ConfidentialKey::Slip77(
+            secp256k1_zkp::SecretKey::from_slice(&[3; 32]).unwrap(),
+        ));
+        assert!(ct_pegin
+            .build_claim_pset(outpoint, 4_750, genesis_hash, asset, 1_000, &secp)
+            .is_ok());
+        assert!(ct_pegin
+            .build_claim_pset(outpoint, 4_749, genesis_hash, asset, 1_000, &secp)
+            .is_err());
+    }
+
+    #[test]
+    fn spending_paths() {
+        let pegin = LegacyPegin::new_legacy_fed(user_desc());
+        let paths = Liftable::spending_paths(&pegin).unwrap();
+        assert_eq!(paths.len(), 2);
+
+        let key_hashes = |pks: &[LegacyPeginKey]| -> Vec<_> {
+            pks.iter()
+                .map(|pk| semantic::Policy::KeyHash(pk.to_pubkeyhash()))
+                .collect()
+        };
+        assert_eq!(
+            paths[0].conditions,
+            vec![semantic::Policy::Threshold(11, key_hashes(&pegin.fed_pks))]
+        );
+        assert_eq!(
+            paths[1].conditions,
+            vec![
+                semantic::Policy::Older(4032),
+                semantic::Policy::Threshold(2, key_hashes(&pegin.emer_pks)),
+            ]
+        );
+    }
+
+    #[test]
+    fn into_and_from_parts() {
+        let mut pegin = LegacyPegin::new_legacy_fed(user_desc());
+        pegin.blinding_key = Some(ConfidentialKey::Slip77(
+            secp256k1_zkp::SecretKey::from_slice(&[3; 32]).unwrap(),
+        ));
+        let (fed_pks, fed_k, emer_pks, emer_k, timelock, desc, blinding_key) =
+            pegin.clone().into_parts();
+        assert_eq!((fed_k, emer_k, timelock), (11, 2, 4032));
+        let rebuilt = LegacyPegin::from_parts(
+            fed_pks.clone(),
+            fed_k,
+            emer_pks.clone(),
+            emer_k,
+            timelock,
+            desc.clone(),
+            blinding_key.clone(),
+        )
+        .unwrap();
+        assert_eq!(rebuilt, pegin);
+
+        // Thresholds are checked instead of panicking
+        for &(fed_k, emer_k) in &[(0, emer_k), (fed_pks.len() + 1, emer_k), (fed_k, 0)] {
+            assert!(LegacyPegin::from_parts(
+                fed_pks.clone(),
+                fed_k,
+                emer_pks.clone(),
+                emer_k,
+                timelock,
+                desc.clone(),
+                None,
+            )
+            .is_err());
+        }
+
+        // Modified parts give a consistent federation script
+        let secp = secp256k1_zkp::Secp256k1::verification_only();
+        let modified =
+            LegacyPegin::from_parts(fed_pks, 10, emer_pks, emer_k, timelock, desc, blinding_key)
+                .unwrap();
+        assert_eq!(modified.fed_k, 10);
+        assert_ne!(
+            modified.bitcoin_witness_script(&secp).unwrap(),
+            pegin.bitcoin_witness_script(&secp).unwrap()
+        );
+        assert_eq!(
+            modified
+                .bitcoin_witness_script(&secp)
+                .unwrap()
+                .instructions()
+                .nth(4)
+                .unwrap()
+                .unwrap(),
+            script::Instruction::Op(opcodes::all::OP_PUSHNUM_10)
+        );
+    }
+
+    #[test]
+    fn claim_script() {
+        let pegin = LegacyPegin::new_legacy_fed(user_desc());
+        let claim_script = pegin.claim_script();
+        assert_eq!(
+            hashes::sha256::Hash::hash(claim_script.as_bytes()),
+            pegin.tweak()
+        );
+        assert_eq!(claim_script, user_desc().script_pubkey());
+
+        // The federation keys in the witness script are tweaked with it
+        let secp = secp256k1_zkp::Secp256k1::verification_only();
+        let tweaked = tweak_key(
+            pegin.fed_pks[0].as_untweaked(),
+            &secp,
+            hashes::sha256::Hash::hash(claim_script.as_bytes()).as_inner(),
+        );
+        let script = pegin.bitcoin_witness_script(&secp).unwrap();
+        assert!(script
+            .as_bytes()
+            .windows(33)
+            .any(|w| w == &tweaked.to_bytes()[..]));
+    }
+
+    #[test]
+    fn claim_script_wsh() {
+        let desc = Descriptor::<bitcoin::PublicKey>::from_str(
+            "elwsh(pk(020000000000000000000000000000000000000000000000000000000000000002))",
+        )
+        .unwrap();
+        let pegin = LegacyPegin::new_legacy_fed(desc.clone());
+        assert_eq!(pegin.claim_script(), desc.script_pubkey());
+        assert_ne!(pegin.claim_script(), desc.explicit_script());
+
+        let secp = secp256k1_zkp::Secp256k1::signing_only();
+        let outpoint = bitcoin::OutPoint::new(bitcoin::Txid::default(), 0);
+        let genesis_hash =
+            bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Bitcoin).block_hash();
+        let asset = elements::AssetId::from_slice(&[7; 32]).unwrap();
+        let pset = pegin
+            .build_claim_pset(outpoint, 100_000, genesis_hash, asset, 1_000, &secp)
+            .unwrap();
+        assert_eq!(
+            pset.inputs[0].pegin_claim_script,
+            Some(desc.script_pubkey())
+        );
+    }
+
+    #[test]
+    fn diff_functionary_key() {
+        let pegin = LegacyPegin::new_legacy_fed(user_desc());
+        assert!(pegin.diff(&pegin).is_empty());
+
+        let old_key = pegin.fed_pks[3].clone();
+        let new_key = LegacyPeginKey::Functionary(
+            bitcoin::PublicKey::from_str(
+                "020000000000000000000000000000000000000000000000000000000000000003",
+            )
+            .unwrap(),
+        );
+        let mut fed_pks = pegin.fed_pks.clone();
+        fed_pks[3] = new_key.clone();
+        let rotated = LegacyPegin::new(
+            fed_pks,
+            pegin.fed_k,
+            pegin.emer_pks.clone(),
+            pegin.emer_k,
+            pegin.timelock,
+            user_desc(),
+        );
+
+        let diff = pegin.diff(&rotated);
+        assert_eq!(diff.added_fed_pks, vec![new_key.clone()]);
+        assert_eq!(diff.removed_fed_pks, vec![old_key.clone()]);
+        assert!(diff.added_emer_pks.is_empty() && diff.removed_emer_pks.is_empty());
+        assert!(!diff.thresholds_changed && !diff.timelock_changed);
+        assert!(diff.user_desc.is_empty());
+        assert_eq!(
+            diff.to_string(),
+            format!("+fed {}, -fed {}", new_key, old_key)
+        );
+    }
+
+    #[test]
+    fn diff_swapped_functionary_keys() {
+        let pegin = LegacyPegin::new_legacy_fed(user_desc());
+        let mut fed_pks = pegin.fed_pks.clone();
+        fed_pks.swap(0, 1);
+        let swapped = LegacyPegin::new(
+            fed_pks,
+            pegin.fed_k,
+            pegin.emer_pks.clone(),
+            pegin.emer_k,
+            pegin.timelock,
+            user_desc(),
+        );
+
+        let diff = pegin.diff(&swapped);
+        assert!(!diff.is_empty());
+        assert!(diff.added_fed_pks.is_empty() && diff.removed_fed_pks.is_empty());
+        assert_eq!(
+            diff.moved_fed_pks,
+            vec![pegin.fed_pks[1].clone(), pegin.fed_pks[0].clone()]
+        );
+
+        let mut ct_pegin = pegin.clone();
+        ct_pegin.blinding_key = Some(ConfidentialKey::Slip77(
+            secp256k1_zkp::SecretKey::from_slice(&[1; 32]).unwrap(),
+        ));
+        let diff = pegin.diff(&ct_pegin);
+        assert!(diff.blinding_key_changed);
+        assert_eq!(diff.to_string(), "blinding key changed");
+    }
+
+    #[test]
+    fn multi_witness_key_order() {
+        let secp = secp256k1_zkp::Secp256k1::new();
+        let keys: Vec<(secp256k1_zkp::SecretKey, bitcoin::PublicKey)> = (1..4u8)
+            .map(|i| {
+                let sk = secp256k1_zkp::SecretKey::from_slice(&[i; 32]).unwrap();
+                let pk = bitcoin::PublicKey {
+                    compressed: true,
+                    key: secp256k1_zkp::PublicKey::from_secret_key(&secp, &sk),
+                };
+                (sk, pk)
+            })
+            .collect();
+        let pks: Vec<_> = keys.iter().map(|&(_, pk)| pk).collect();
+
+        // Find a message for which the last key has the shortest signature
+        let sigs = (0..=255u8)
+            .map(|m| {
+                let msg = secp256k1_zkp::Message::from_slice(&[m; 32]).unwrap();
+                keys.iter()
+                    .map(|&(ref sk, pk)| (pk, (secp.sign(&msg, sk), bitcoin::SigHashType::All)))
+                    .collect::<HashMap<_, _>>()
+            })
+            .find(|sigs| {
+                let len = |pk| sigs[pk].0.serialize_der().len();
+                len(&pks[2]) < len(&pks[0]) && len(&pks[2]) < len(&pks[1])
+            })
+            .unwrap();
+        let ser = |pk: &bitcoin::PublicKey| {
+            let mut sig = sigs[pk].0.serialize_der().to_vec();
+            sig.push(bitcoin::SigHashType::All.as_u32() as u8);
+            sig
+        };
+
+        // The shortest signature is kept, but pushed in key order
+        let wit = multi_witness(&sigs, &pks, 2).unwrap();
+        assert_eq!(wit.len(), 2);
+        assert_eq!(wit[1], ser(&pks[2]));
+        assert!(wit[0] == ser(&pks[0]) || wit[0] == ser(&pks[1]));
+        assert_eq!(
+            multi_witness(&sigs, &pks, 3).unwrap(),
+            pks.iter().map(ser).collect::<Vec<_>>()
+        );
+        assert!(multi_witness(&sigs, &pks[..1], 2).is_none());
+    }
+
+    #[test]
+    fn satisfaction_branch_selection() {
+        let secp = secp256k1_zkp::Secp256k1::new();
+        let keys: Vec<(secp256k1_zkp::SecretKey, bitcoin::PublicKey)> = (1..8u8)
+            .map(|i| {
+                let sk = secp256k1_zkp::SecretKey::from_slice(&[i; 32]).unwrap();
+                let pk = bitcoin::PublicKey {
+                    compressed: true,
+                    key: secp256k1_zkp::PublicKey::from_secret_key(&secp, &sk),
+                };
+                (sk, pk)
+            })
+            .collect();
+        let fed_pks = keys[..5]
+            .iter()
+            .map(|&(_, pk)| LegacyPeginKey::Functionary(pk))
+            .collect();
+        let emer_pks = keys[5..]
+            .iter()
+            .map(|&(_, pk)| LegacyPeginKey::Functionary(pk))
+            .collect();
+        let pegin = LegacyPegin::new(fed_pks, 3, emer_pks, 1, 4032, user_desc());
+
+        // Signatures are not verified, so any signature will do
+        let msg = secp256k1_zkp::Message::from_slice(&[1; 32]).unwrap();
+        let sig = (secp.sign(&msg, &keys[0].0), bitcoin::SigHashType::All);
+        let tweak = pegin.tweak();
+        let mut sigs = HashMap::new();
+        for (_, pk) in &keys[..5] {
+            sigs.insert(tweak_key(pk, &secp, tweak.as_inner()), sig);
+        }
+        sigs.insert(keys[5].1, sig);
+
+        let (std_wit, _) = pegin
+            .get_bitcoin_satisfaction_for(&secp, (&sigs, BtcOlder(4032)), OptimizeFor::Standardness)
+            .unwrap();
+        assert_eq!(std_wit.len(), 5);
+        assert_eq!(
+            pegin
+                .get_bitcoin_satisfaction(&secp, (&sigs, BtcOlder(4032)))
+                .unwrap()
+                .0,
+            std_wit
+        );
+        let (fee_wit, _) = pegin
+            .get_bitcoin_satisfaction_for(&secp, (&sigs, BtcOlder(4032)), OptimizeFor::Fee)
+            .unwrap();
+        assert_eq!(fee_wit.len(), 3);
+        assert!(witness_size(&fee_wit) < witness_size(&std_wit));
+
+        // The emergency branch is only cheaper once its timelock is met
+        let (wit, _) = pegin
+            .get_bitcoin_satisfaction_for(&secp, &sigs, OptimizeFor::Fee)
+            .unwrap();
+        assert_eq!(wit, std_wit);
+
+        // Nor is it a fallback before then
+        let mut emer_sigs = HashMap::new();
+        emer_sigs.insert(keys[5].1, sig);
+        for &optimize in &[OptimizeFor::Standardness, OptimizeFor::Fee] {
+            match pegin.get_bitcoin_satisfaction_for(&secp, &emer_sigs, optimize) {
+                Err(Error::CouldNotSatisfy) => {}
+                res => panic!("unexpected result {:?}", res),
+            }
+            let (wit, _) = pegin
+                .get_bitcoin_satisfaction_for(&secp, (&emer_sigs, BtcOlder(4032)), optimize)
+                .unwrap();
+            assert_eq!(wit.len(), 3);
+        }
+    }
+
+    #[test]
+    fn bytes_roundtrip() {
+        let pegin = LegacyPegin::new_legacy_fed(user_desc());
+        let bytes = pegin.to_bytes();
+        assert_eq!(bytes[0], LEGACY_PEGIN_ENCODING_VERSION);
+        let decoded = LegacyPegin::<bitcoin::PublicKey>::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, pegin);
+        assert_eq!(decoded.to_bytes(), bytes);
+
+        // A large user miniscript, with a confidential user descriptor
+        let pks: Vec<String> = pegin
+            .fed_pks
+            .iter()
+            .map(|pk| pk.as_untweaked().to_string())
+            .collect();
+        let user = Descriptor::<bitcoin::PublicKey>::from_str(&format!(
+            "elwsh(or_d(multi(11,{}),and_v(v:older(1000),pk({}))))",
+            pks.join(","),
+            pks[0]
+        ))
+        .unwrap();
+        let mut pegin = LegacyPegin::new_legacy_fed(user);
+        pegin.blinding_key = Some(ConfidentialKey::Slip77(
+            secp256k1_zkp::SecretKey::from_slice(&[3; 32]).unwrap(),
+        ));
+        let bytes = pegin.to_bytes();
+        assert!(bytes.len() > 1000);
+        let decoded = LegacyPegin::<bitcoin::PublicKey>::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, pegin);
+        assert_eq!(decoded.to_bytes(), bytes);
+
+        // Invalid encodings are rejected
+        let mut bad_version = bytes.clone();
+        bad_version[0] = 2;
+        assert!(LegacyPegin::<bitcoin::PublicKey>::from_bytes(&bad_version).is_err());
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(LegacyPegin::<bitcoin::PublicKey>::from_bytes(&trailing).is_err());
+        assert!(LegacyPegin::<bitcoin::PublicKey>::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut bad_threshold = bytes.clone();
+        // The federation threshold follows its 15 keys
+        bad_threshold[2 + 15 * 34] = 16;
+        assert!(LegacyPegin::<bitcoin::PublicKey>::from_bytes(&bad_threshold).is_err());
+    }
+
+    #[test]
+    fn hash_map_lookup() {
+        let pegin = LegacyPegin::new_legacy_fed(user_desc());
+        let other_desc = Descriptor::from_str(
+            "elwpkh(020000000000000000000000000000000000000000000000000000000000000003)",
+        )
+        .unwrap();
+        let other = LegacyPegin::new_legacy_fed(other_desc);
+
+        let mut cache = HashMap::new();
+        cache.insert(pegin.clone(), 1);
+        cache.insert(other.clone(), 2);
+        assert_eq!(cache.len(), 2);
+
+        let reparsed = LegacyPegin::<bitcoin::PublicKey>::from_str(&pegin.to_string()).unwrap();
+        assert_eq!(cache.get(&reparsed), Some(&1));
+        let reparsed = LegacyPegin::<bitcoin::PublicKey>::from_str(&other.to_string()).unwrap();
+        assert_eq!(cache.get(&reparsed), Some(&2));
+    }
+
+    #[test]
+    fn sanity_check_user_descriptor() {
+        let pegin = LegacyPegin::new_legacy_fed(user_desc());
+        pegin.sanity_check().unwrap();
+
+        let bare = Descriptor::<bitcoin::PublicKey>::from_str(
+            "elpk(020000000000000000000000000000000000000000000000000000000000000002)",
+        )
+        .unwrap();
+        match LegacyPegin::new_legacy_fed(bare).sanity_check() {
+            Err(Error::PeginError(PeginError::UnspendableUserDescriptor)) => {}
+            res => panic!("unexpected {:?}", res),
+        }
+
+        // A repeated key fails the user descriptor's own sanity check
+        let insane = Descriptor::<bitcoin::PublicKey>::from_str(
+            "elwsh(and_b(\
+             pk(020000000000000000000000000000000000000000000000000000000000000002),\
+             s:pk(020000000000000000000000000000000000000000000000000000000000000002)))",
+        )
+        .unwrap();
+        match LegacyPegin::new_legacy_fed(insane).sanity_check() {
+            Err(Error::PeginError(PeginError::InsaneUserDescriptor)) => {}
+            res => panic!("unexpected {:?}", res),
+        }
+    }
+
+    #[test]
+    fn to_public_strips_secrets() {
+        let secp = secp256k1_zkp::Secp256k1::signing_only();
+        let with_secret = LegacyPegin::new_legacy_fed(
+            Descriptor::<String>::from_str("elwpkh(xprv9s21ZrQH143K4CTb63EaMxja1YiTnSEWKMbn23uoEnAzxjdUJRQkazCAtzxGm4LSoTSVTptoV9RbchnKPW9HxKtZumdyxyikZFDLhogJ5Uj/44'/0'/0'/0/*)").unwrap(),
+        );
+        let public = with_secret.to_public(&secp).unwrap();
+        assert_eq!(
+            format!("{:#}", public.desc),
+            "elwpkh([a12b02f4/44'/0'/0']xpub6BzhLAQUDcBUfHRQHZxDF2AbcJqp4Kaeq6bzJpXrjrWuK26ymTFwkEFbxPra2bJ7yeZKbDjfDeFwxe93JMqpo5SsPJH6dZdvV9kMzJkAZ69/0/*)"
+        );
+        assert_eq!(public.fed_pks, with_secret.fed_pks);
+        assert_eq!(public.emer_pks, with_secret.emer_pks);
+        assert_eq!(
+            LegacyPegin::<DescriptorPublicKey>::from_str(&public.to_string()).unwrap(),
+            public
+        );
+
+        // Already public pegins are unchanged
+        let public_str = LegacyPegin::<String>::from_str(&public.to_string()).unwrap();
+        assert_eq!(public_str.to_public(&secp).unwrap(), public);
+    }
+
+    #[test]
+    fn witness_script_with_key_hash() {
+        let pegin = LegacyPegin::new_legacy_fed(user_desc());
+        let secp = secp256k1_zkp::Secp256k1::verification_only();
+
+        // Replace the emergency multisig with a key hash
+        let fed_ms =
+            BtcMiniscript::from_ast(BtcTerminal::Multi(pegin.fed_k, pegin.fed_pks.clone()))
+                .unwrap();
+        let csv = BtcMiniscript::from_ast(BtcTerminal::Verify(Arc::new(
+            BtcMiniscript::from_ast(BtcTerminal::Older(pegin.timelock)).unwrap(),
+        )))
+        .unwrap();
+        let pkh = BtcMiniscript::from_ast(BtcTerminal::Check(Arc::new(
+            BtcMiniscript::from_ast(BtcTerminal::PkH(pegin.emer_pks[0].to_pubkeyhash())).unwrap(),
+        )))
+        .unwrap();
+        let emer_ms =
+            BtcMiniscript::from_ast(BtcTerminal::AndV(Arc::new(csv), Arc::new(pkh))).unwrap();
+        let ms =
+            BtcMiniscript::from_ast(BtcTerminal::OrD(Arc::new(fed_ms), Arc::new(emer_ms))).unwrap();
+        let pegin = LegacyPegin { ms, ..pegin };
+
+        match pegin.bitcoin_witness_script(&secp) {
+            Err(Error::PeginError(PeginError::UnsupportedKeyHash)) => {}
+            res => panic!("unexpected {:?}", res),
+        }
+        assert!(pegin
+            .bitcoin_address(bitcoin::Network::Bitcoin, &secp)
+            .is_err());
+        assert!(pegin.bitcoin_script_pubkey(&secp).is_err());
+        assert!(pegin.max_satisfaction_weight().is_err());
+    }
+
+    #[test]
+    fn witness_templates() {
+        let pegin = LegacyPegin::new_legacy_fed(user_desc());
+        let (fed, emer) = pegin.witness_templates();
+
+        assert_eq!(fed.len(), pegin.fed_k + 2);
+        assert_eq!(fed[0], WitnessItem::Dummy);
+        assert_eq!(
+            fed[1..=pegin.fed_k].to_vec(),
+            pegin.fed_pks[..pegin.fed_k]
+                .iter()
+                .map(|pk| WitnessItem::Signature(pk.clone()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(fed[pegin.fed_k + 1], WitnessItem::Script);
+
+        assert_eq!(
+            emer,
+            vec![
+                WitnessItem::Dummy,
+                WitnessItem::Signature(pegin.emer_pks[0].clone()),
+                WitnessItem::Signature(pegin.emer_pks[1].clone()),
+                WitnessItem::Script,
+            ]
+        );
+    }
+
+    #[test]
+    fn rotate_emergency_keys() {
+        let secp = secp256k1_zkp::Secp256k1::new();
+        let pegin = LegacyPegin::new_legacy_fed(user_desc());
+        let new_emer_pks = pegin.fed_pks[..3].to_vec();
+        let rotated = pegin.with_emergency(new_emer_pks.clone(), 2, 1000).unwrap();
+        assert_eq!(rotated.emer_pks, new_emer_pks);
+        assert_eq!((rotated.emer_k, rotated.timelock), (2, 1000));
+        assert_eq!(rotated.fed_pks, pegin.fed_pks);
+        assert_eq!(rotated.fed_k, pegin.fed_k);
+        assert_eq!(rotated.desc, pegin.desc);
+        // Same as building the pegin from scratch
+        assert_eq!(
+            rotated,
+            LegacyPegin::new(
+                pegin.fed_pks.clone(),
+                pegin.fed_k,
+                new_emer_pks.clone(),
+                2,
+                1000,
+                user_desc()
+            )
+        );
+
+        // The federation witness and its part of the witness script are unchanged
+        let msg = secp256k1_zkp::Message::from_slice(&[1; 32]).unwrap();
+        let sk = secp256k1_zkp::SecretKey::from_slice(&[1; 32]).unwrap();
+        let sig = (secp.sign(&msg, &sk), bitcoin::SigHashType::All);
+        let tweak = pegin.tweak();
+        let sigs: HashMap<_, _> = pegin
+            .fed_pks
+            .iter()
+            .map(|pk| (tweak_key(pk.as_untweaked(), &secp, tweak.as_inner()), sig))
+            .collect();
+        // (only the trailing witness script differs)
+        let wit = pegin.get_bitcoin_satisfaction(&secp, &sigs).unwrap().0;
+        let rotated_wit = rotated.get_bitcoin_satisfaction(&secp, &sigs).unwrap().0;
+        assert_eq!(wit[..wit.len() - 1], rotated_wit[..rotated_wit.len() - 1]);
+        // OP_DEPTH <k + 1> OP_EQUAL OP_IF <k> <keys> <n> OP_ELSE
+        let fed_len = 7 + 34 * pegin.fed_pks.len();
+        assert_eq!(
+            pegin.bitcoin_witness_script(&secp).unwrap()[..fed_len],
+            rotated.bitcoin_witness_script(&secp).unwrap()[..fed_len]
+        );
+        assert_ne!(
+            pegin.bitcoin_witness_script(&secp).unwrap(),
+            rotated.bitcoin_witness_script(&secp).unwrap()
+        );
+
+        assert!(pegin.with_emergency(new_emer_pks.clone(), 4, 1000).is_err());
+        assert!(pegin.with_emergency(new_emer_pks, 0, 1000).is_err());
+    }
+
+    #[test]
+    fn required_bitcoin_signatures() {
+        let secp = secp256k1_zkp::Secp256k1::verification_only();
+        let pegin = LegacyPegin::new_legacy_fed(user_desc());
+        let tweak = pegin.tweak();
+        let expected: Vec<_> = pegin
+            .fed_pks
+            .iter()
+            .map(|pk| tweak_key(pk.as_untweaked(), &secp, tweak.as_inner()))
+            .chain(pegin.emer_pks.iter().map(|pk| *pk.as_untweaked()))
+            .map(|pk| (pk, bitcoin::SigHashType::AllPlusAnyoneCanPay))
+            .collect();
+        assert_eq!(
+            pegin.required_bitcoin_signatures(&secp, bitcoin::SigHashType::AllPlusAnyoneCanPay),
+            expected
+        );
+    }
+
+    #[test]
+    fn parse_multi_line() {
+        let pegin = LegacyPegin::new_legacy_fed(user_desc());
+        let lines = format!("{:#}", pegin).replace(",", ",\n    ");
+        let parsed = LegacyPegin::<bitcoin::PublicKey>::from_str(&lines).unwrap();
+        assert_eq!(parsed, pegin);
+
+        // A checksum covers the exact string, and newlines have none
+        let with_checksum = format!("{}#{}", lines, pegin.to_string().split('#').nth(1).unwrap());
+        assert!(LegacyPegin::<bitcoin::PublicKey>::from_str(&with_checksum).is_err());
+        let spaced = format!("{:#}", pegin).replace(",", ", ");
+        let spaced = format!("{}#{}", spaced, desc_checksum(&spaced).unwrap());
+        let parsed = LegacyPegin::<bitcoin::PublicKey>::from_str(&spaced).unwrap();
+        assert_eq!(parsed, pegin);
+
+        // Whitespace inside a key is an error
+        let split_key = format!("{:#}", pegin).replacen("020e03", "020e 03", 1);
+        assert!(LegacyPegin::<bitcoin::PublicKey>::from_str(&split_key).is_err());
+    }
+
+    #[test]
+    fn cms_extra_elements() {
+        let secp = secp256k1_zkp::Secp256k1::new();
+        let pegin = LegacyPegin::new_legacy_fed(user_desc());
+        assert_eq!(pegin.cms_extra_elements(), 1);
+
+        // Signatures are not verified, so any signature will do
+        let msg = secp256k1_zkp::Message::from_slice(&[1; 32]).unwrap();
+        let sk = secp256k1_zkp::SecretKey::from_slice(&[1; 32]).unwrap();
+        let sig = (secp.sign(&msg, &sk), bitcoin::SigHashType::All);
+        let tweak = pegin.tweak();
+        let sigs: HashMap<_, _> = pegin
+            .fed_pks
+            .iter()
+            .map(|pk| (tweak_key(pk.as_untweaked(), &secp, tweak.as_inner()), sig))
+            .collect();
+
+        let (wit, _) = pegin.get_bitcoin_satisfaction(&secp, &sigs).unwrap();
+        assert_eq!(wit.len(), pegin.fed_k + pegin.cms_extra_elements() + 1);
+        // The empty dummy comes first, below the signatures
+        assert_eq!(wit[0], Vec::<u8>::new());
+        assert!(wit[1..=pegin.fed_k].iter().all(|sig| !sig.is_empty()));
+        assert_eq!(
+            wit[pegin.fed_k + 1],
+            pegin.bitcoin_witness_script(&secp).unwrap().into_bytes()
+        );
+        assert_eq!(wit.len(), pegin.witness_templates().0.len());
+    }
+}
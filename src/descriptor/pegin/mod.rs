@@ -31,6 +31,7 @@ use elements::secp256k1_zkp;
 use expression::{self, FromTree};
 use policy::{semantic, Liftable};
 use std::{
+    cell::RefCell,
     fmt::Debug,
     fmt::{self, Display},
     marker::PhantomData,
@@ -49,11 +50,39 @@ use {DescriptorTrait, Segwitv0, TranslatePk};
 use {tweak_key, util::varint_len};
 
 use super::checksum::{desc_checksum, verify_checksum};
+use super::DescriptorType;
 use {MiniscriptKey, ToPublicKey};
 
 mod dynafed_pegin;
+mod error;
 mod legacy_pegin;
-pub use self::legacy_pegin::{LegacyPegin, LegacyPeginKey};
+pub use self::error::PeginError;
+pub use self::legacy_pegin::{
+    LegacyPegin, LegacyPeginDiff, LegacyPeginKey, LegacyPeginParts, PeginCost, WitnessItem,
+};
+
+/// Which spending branch to prefer when more than one can be satisfied
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OptimizeFor {
+    /// Prefer the federation branch, as functionaries expect
+    Standardness,
+    /// Prefer the branch with the smallest witness
+    Fee,
+}
+
+/// Computes the tweak applied to the federation keys of a pegin
+/// from the user descriptor alone. The tweak is the sha256 hash of
+/// the explicit script of the user descriptor.
+pub fn pegin_tweak_from_user_descriptor<Pk>(desc: &Descriptor<Pk>) -> hashes::sha256::Hash
+where
+    Pk: MiniscriptKey + ToPublicKey + FromStr,
+    Pk::Hash: FromStr,
+    <Pk as FromStr>::Err: ToString,
+    <<Pk as MiniscriptKey>::Hash as FromStr>::Err: ToString,
+{
+    hashes::sha256::Hash::hash(&desc.explicit_script().into_bytes())
+}
+
 /// A general trait for Pegin Bitcoin descriptor.
 /// It should also support FromStr, fmt::Display and should be liftable
 /// to bitcoin Semantic Policy.
@@ -85,13 +114,29 @@ pub trait PeginTrait<Pk: MiniscriptKey> {
 
     /// Computes the bitcoin scriptpubkey of the descriptor.
     /// Requires the secp context to compute the tweak
+    /// Errors when the witness script cannot be computed, e.g. for
+    /// key hashes in the federation script
     fn bitcoin_script_pubkey<C: secp256k1_zkp::Verification>(
         &self,
         secp: &secp256k1_zkp::Secp256k1<C>,
-    ) -> BtcScript
+    ) -> Result<BtcScript, Error>
     where
         Pk: ToPublicKey;
 
+    /// Computes the bitcoin scriptpubkey of the descriptor on the
+    /// given network.
+    /// Requires the secp context to compute the tweak
+    fn bitcoin_script_pubkey_for_network<C: secp256k1_zkp::Verification>(
+        &self,
+        network: bitcoin::Network,
+        secp: &secp256k1_zkp::Secp256k1<C>,
+    ) -> Result<BtcScript, Error>
+    where
+        Pk: ToPublicKey,
+    {
+        Ok(self.bitcoin_address(network, secp)?.script_pubkey())
+    }
+
     /// Computes the scriptSig that will be in place for an unsigned
     /// input spending an output with this descriptor. For pre-segwit
     /// descriptors, which use the scriptSig for signatures, this
@@ -104,7 +149,7 @@ pub trait PeginTrait<Pk: MiniscriptKey> {
     fn bitcoin_unsigned_script_sig<C: secp256k1_zkp::Verification>(
         &self,
         secp: &secp256k1_zkp::Secp256k1<C>,
-    ) -> BtcScript
+    ) -> Result<BtcScript, Error>
     where
         Pk: ToPublicKey;
 
@@ -112,28 +157,16 @@ pub trait PeginTrait<Pk: MiniscriptKey> {
     /// script before any hashing is done. For `Bare`, `Pkh` and `Wpkh` this
     /// is the scriptPubkey; for `ShWpkh` and `Sh` this is the redeemScript;
     /// for the others it is the witness script.
-    /// `to_pk_ctx` denotes the ToPkCtx required for deriving bitcoin::PublicKey
-    /// from MiniscriptKey using [ToPublicKey].
-    /// If MiniscriptKey is already is [bitcoin::PublicKey], then the context
-    /// would be [NullCtx] and [descriptor.DescriptorPublicKeyCtx] if MiniscriptKey is [descriptor.DescriptorPublicKey]
-    ///
-    /// In general, this is defined by generic for the trait [ToPublicKey]
     fn bitcoin_witness_script<C: secp256k1_zkp::Verification>(
         &self,
         secp: &secp256k1_zkp::Secp256k1<C>,
-    ) -> BtcScript
+    ) -> Result<BtcScript, Error>
     where
         Pk: ToPublicKey;
 
     /// Returns satisfying witness and scriptSig to spend an
     /// output controlled by the given descriptor if it possible to
     /// construct one using the satisfier S.
-    /// `to_pk_ctx` denotes the ToPkCtx required for deriving bitcoin::PublicKey
-    /// from MiniscriptKey using [ToPublicKey].
-    /// If MiniscriptKey is already is [bitcoin::PublicKey], then the context
-    /// would be [NullCtx] and [descriptor.DescriptorPublicKeyCtx] if MiniscriptKey is [descriptor.DescriptorPublicKey]
-    ///
-    /// In general, this is defined by generic for the trait [ToPublicKey]
     fn get_bitcoin_satisfaction<S, C: secp256k1_zkp::Verification>(
         &self,
         secp: &secp256k1_zkp::Secp256k1<C>,
@@ -169,20 +202,42 @@ pub trait PeginTrait<Pk: MiniscriptKey> {
     /// scriptSig and witness stack length.
     fn max_satisfaction_weight(&self) -> Result<usize, Error>;
 
+    /// Computes an upper bound on the virtual size of a satisfying witness
+    /// to the transaction, i.e. [PeginTrait::max_satisfaction_weight]
+    /// divided by 4 and rounded up.
+    fn max_satisfaction_vsize(&self) -> Result<usize, Error> {
+        Ok((self.max_satisfaction_weight()? + 3) / 4)
+    }
+
+    /// Computes an upper bound on the weight of a satisfying witness for
+    /// spending the elements-side funds controlled by the user descriptor
+    /// once the pegin has been claimed. Returns `None` if the user
+    /// descriptor has no satisfaction.
+    ///
+    /// The default implementation clones the pegin to compute the weight
+    /// of [PeginTrait::into_user_descriptor].
+    fn elements_spend_weight(&self) -> Option<usize>
+    where
+        Self: Clone,
+        Pk: FromStr,
+        Pk::Hash: FromStr,
+        <Pk as FromStr>::Err: ToString,
+        <<Pk as MiniscriptKey>::Hash as FromStr>::Err: ToString,
+    {
+        self.clone()
+            .into_user_descriptor()
+            .max_satisfaction_weight()
+            .ok()
+    }
+
     /// Get the `scriptCode` of a transaction output.
     ///
     /// The `scriptCode` is the Script of the previous transaction output being serialized in the
     /// sighash when evaluating a `CHECKSIG` & co. OP code.
-    /// `to_pk_ctx` denotes the ToPkCtx required for deriving bitcoin::PublicKey
-    /// from MiniscriptKey using [ToPublicKey].
-    /// If MiniscriptKey is already is [bitcoin::PublicKey], then the context
-    /// would be [NullCtx] and [descriptor.DescriptorPublicKeyCtx] if MiniscriptKey is [descriptor.DescriptorPublicKey]
-    ///
-    /// In general, this is defined by generic for the trait [ToPublicKey]
     fn script_code<C: secp256k1_zkp::Verification>(
         &self,
         secp: &secp256k1_zkp::Secp256k1<C>,
-    ) -> BtcScript
+    ) -> Result<BtcScript, Error>
     where
         Pk: ToPublicKey;
 
@@ -191,4 +246,246 @@ pub trait PeginTrait<Pk: MiniscriptKey> {
     /// Users can use the DescrpitorTrait operations on the output Descriptor
     /// to obtain the characteristics of the elements descriptor.
     fn into_user_descriptor(self) -> Descriptor<Pk>;
+
+    /// The bitcoin keys whose signatures may be used to satisfy the pegin,
+    /// in the order the satisfier is asked for them, paired with the
+    /// `sighash` to sign with. These are the candidates of all spending
+    /// branches, more than any single satisfaction needs.
+    /// Requires the secp context to compute the tweak
+    fn required_bitcoin_signatures<C: secp256k1_zkp::Verification>(
+        &self,
+        secp: &secp256k1_zkp::Secp256k1<C>,
+        sighash: bitcoin::SigHashType,
+    ) -> Vec<(bitcoin::PublicKey, bitcoin::SigHashType)>
+    where
+        Pk: ToPublicKey,
+    {
+        let probe = BtcSigProbe(RefCell::new(vec![]));
+        let _ = self.get_bitcoin_satisfaction(secp, &probe);
+        probe
+            .0
+            .into_inner()
+            .into_iter()
+            .map(|pk| (pk, sighash))
+            .collect()
+    }
+}
+
+// Records the keys a pegin asks signatures for without providing any.
+// All timelocks count as met, so that timelocked branches are probed too.
+struct BtcSigProbe(RefCell<Vec<bitcoin::PublicKey>>);
+
+impl BtcSatisfier<bitcoin::PublicKey> for BtcSigProbe {
+    fn lookup_sig(&self, pk: &bitcoin::PublicKey) -> Option<bitcoin_miniscript::BitcoinSig> {
+        let mut pks = self.0.borrow_mut();
+        if !pks.contains(pk) {
+            pks.push(*pk);
+        }
+        None
+    }
+
+    fn check_older(&self, _: u32) -> bool {
+        true
+    }
+
+    fn check_after(&self, _: u32) -> bool {
+        true
+    }
+}
+
+/// A descriptor that is either a regular elements [Descriptor] or a
+/// pegin descriptor. Useful when the caller does not know in advance
+/// whether the string being parsed describes a pegin.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq)]
+pub enum PeginAwareDescriptor<Pk: MiniscriptKey> {
+    /// A regular elements descriptor
+    Elements(Descriptor<Pk>),
+    /// A legacy pegin descriptor
+    LegacyPegin(Box<LegacyPegin<Pk>>),
+}
+
+impl<Pk: MiniscriptKey> PeginAwareDescriptor<Pk> {
+    /// Get the [DescriptorType] of the descriptor
+    pub fn desc_type(&self) -> DescriptorType {
+        match *self {
+            PeginAwareDescriptor::Elements(ref desc) => desc.desc_type(),
+            PeginAwareDescriptor::LegacyPegin(..) => DescriptorType::LegacyPegin,
+        }
+    }
+
+    /// Whether the descriptor is a pegin descriptor
+    pub fn is_pegin(&self) -> bool {
+        match *self {
+            PeginAwareDescriptor::Elements(..) => false,
+            PeginAwareDescriptor::LegacyPegin(..) => true,
+        }
+    }
+
+    /// Get the underlying elements descriptor, if this is not a pegin
+    pub fn as_elements(&self) -> Option<&Descriptor<Pk>> {
+        match *self {
+            PeginAwareDescriptor::Elements(ref desc) => Some(desc),
+            PeginAwareDescriptor::LegacyPegin(..) => None,
+        }
+    }
+
+    /// Get the underlying legacy pegin descriptor, if this is one
+    pub fn as_legacy_pegin(&self) -> Option<&LegacyPegin<Pk>> {
+        match *self {
+            PeginAwareDescriptor::Elements(..) => None,
+            PeginAwareDescriptor::LegacyPegin(ref pegin) => Some(&**pegin),
+        }
+    }
+
+    /// Get the elements descriptor that controls the funds on the
+    /// elements chain. For pegins, this is the user descriptor used
+    /// at claim time.
+    pub fn user_descriptor(&self) -> &Descriptor<Pk> {
+        match *self {
+            PeginAwareDescriptor::Elements(ref desc) => desc,
+            PeginAwareDescriptor::LegacyPegin(ref pegin) => &pegin.desc,
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey> PeginAwareDescriptor<Pk>
+where
+    Pk: FromStr,
+    Pk::Hash: FromStr,
+    <Pk as FromStr>::Err: ToString,
+    <<Pk as MiniscriptKey>::Hash as FromStr>::Err: ToString,
+{
+    /// Whether the descriptor is safe. For pegins, this checks both the
+    /// federation script and the user descriptor.
+    pub fn sanity_check(&self) -> Result<(), Error> {
+        match *self {
+            PeginAwareDescriptor::Elements(ref desc) => desc.sanity_check(),
+            PeginAwareDescriptor::LegacyPegin(ref pegin) => pegin.sanity_check(),
+        }
+    }
+
+    /// Computes the bitcoin address of the pegin descriptor.
+    /// Errors for elements descriptors as they have no bitcoin address.
+    pub fn bitcoin_address<C: secp256k1_zkp::Verification>(
+        &self,
+        network: bitcoin::Network,
+        secp: &secp256k1_zkp::Secp256k1<C>,
+    ) -> Result<bitcoin::Address, Error>
+    where
+        Pk: ToPublicKey,
+    {
+        match *self {
+            PeginAwareDescriptor::Elements(..) => Err(Error::BadDescriptor(String::from(
+                "Elements descriptors don't have a bitcoin address",
+            ))),
+            PeginAwareDescriptor::LegacyPegin(ref pegin) => pegin.bitcoin_address(network, secp),
+        }
+    }
+
+    /// Computes the bitcoin scriptpubkey of the pegin descriptor.
+    /// Errors for elements descriptors.
+    pub fn bitcoin_script_pubkey<C: secp256k1_zkp::Verification>(
+        &self,
+        secp: &secp256k1_zkp::Secp256k1<C>,
+    ) -> Result<BtcScript, Error>
+    where
+        Pk: ToPublicKey,
+    {
+        match *self {
+            PeginAwareDescriptor::Elements(..) => Err(Error::BadDescriptor(String::from(
+                "Elements descriptors don't have a bitcoin scriptpubkey",
+            ))),
+            PeginAwareDescriptor::LegacyPegin(ref pegin) => pegin.bitcoin_script_pubkey(secp),
+        }
+    }
+}
+
+/// Lifts the policy of the funds on the elements chain.
+/// For pegins, this is the policy of the user descriptor, unlike the
+/// [Liftable] implementation of [LegacyPegin] itself, which lifts the
+/// federation policy guarding the deposit on the bitcoin chain. Use
+/// [PeginAwareDescriptor::as_legacy_pegin] to lift the latter.
+impl<Pk: MiniscriptKey> Liftable<Pk> for PeginAwareDescriptor<Pk> {
+    fn lift(&self) -> Result<semantic::Policy<Pk>, Error> {
+        self.user_descriptor().lift()
+    }
+}
+
+impl<Pk: MiniscriptKey> fmt::Debug for PeginAwareDescriptor<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PeginAwareDescriptor::Elements(ref desc) => write!(f, "{:?}", desc),
+            PeginAwareDescriptor::LegacyPegin(ref pegin) => write!(f, "{:?}", pegin),
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for PeginAwareDescriptor<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PeginAwareDescriptor::Elements(ref desc) => fmt::Display::fmt(desc, f),
+            PeginAwareDescriptor::LegacyPegin(ref pegin) => fmt::Display::fmt(pegin, f),
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey> FromStr for PeginAwareDescriptor<Pk>
+where
+    Pk: FromStr,
+    Pk::Hash: FromStr,
+    <Pk as FromStr>::Err: ToString,
+    <<Pk as MiniscriptKey>::Hash as FromStr>::Err: ToString,
+{
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("legacy_pegin(") {
+            Ok(PeginAwareDescriptor::LegacyPegin(Box::new(
+                LegacyPegin::from_str(s)?,
+            )))
+        } else {
+            Ok(PeginAwareDescriptor::Elements(Descriptor::from_str(s)?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_pegin_aware_descriptor() {
+        let user = "elwpkh(020000000000000000000000000000000000000000000000000000000000000002)";
+        let desc = PeginAwareDescriptor::<bitcoin::PublicKey>::from_str(user).unwrap();
+        assert!(!desc.is_pegin());
+        assert_eq!(desc.desc_type(), DescriptorType::Wpkh);
+        assert!(desc.as_legacy_pegin().is_none());
+        assert!(desc.sanity_check().is_ok());
+        assert_eq!(
+            PeginAwareDescriptor::from_str(&desc.to_string()).unwrap(),
+            desc
+        );
+        let secp = secp256k1_zkp::Secp256k1::verification_only();
+        assert!(desc.bitcoin_script_pubkey(&secp).is_err());
+        assert!(desc
+            .bitcoin_address(bitcoin::Network::Bitcoin, &secp)
+            .is_err());
+
+        let pegin = PeginAwareDescriptor::<bitcoin::PublicKey>::from_str(&format!(
+            "legacy_pegin(or_d(multi(1,\
+             f020e0338c96a8870479f2396c373cc7696ba124e8635d41b0ea581112b67817261,\
+             f02675333a4e4b8fb51d9d4e22fa5a8eaced3fdac8a8cbf9be8c030f75712e6af99),\
+             and_v(v:older(4032),multi(1,\
+             u03aab896d53a8e7d6433137bbba940f9c521e085dd07e60994579b64a6d992cf79))),{})",
+            user
+        ))
+        .unwrap();
+        assert!(pegin.is_pegin());
+        assert_eq!(pegin.desc_type(), DescriptorType::LegacyPegin);
+        assert_eq!(pegin.as_legacy_pegin().unwrap().timelock, 4032);
+        assert_eq!(pegin.user_descriptor(), desc.as_elements().unwrap());
+        assert_eq!(pegin.lift().unwrap(), desc.lift().unwrap());
+        assert!(pegin.sanity_check().is_ok());
+        assert!(pegin.bitcoin_script_pubkey(&secp).is_ok());
+    }
 }
@@ -23,6 +23,7 @@ use elements::{self, Script};
 
 use expression::{self, FromTree};
 use miniscript::context::{ScriptContext, ScriptContextError};
+use miniscript::satisfy::elementssig_to_rawsig_preferred;
 use policy::{semantic, Liftable};
 use util::varint_len;
 use {
@@ -31,9 +32,29 @@ use {
 };
 
 use super::{
-    checksum::{desc_checksum, verify_checksum},
+    checksum::{verify_checksum, write_with_checksum},
     DescriptorTrait, ElementsTrait, SortedMultiVec, ELMTS_STR,
 };
+
+/// Checks that a segwit descriptor does not contain any uncompressed
+/// keys. Uncompressed keys are invalid in segwit contexts; legacy
+/// descriptors (`Bare`, `Pkh`, `Sh`) are allowed to contain them.
+/// Keys only present as hashes cannot be checked.
+pub(super) fn check_compressed_keys<Pk, T>(desc: &T) -> Result<(), Error>
+where
+    Pk: MiniscriptKey,
+    T: ForEachKey<Pk>,
+{
+    let has_uncompressed = desc.for_any_key(|key| match key {
+        ForEach::Key(pk) => pk.is_uncompressed(),
+        ForEach::Hash(..) => false,
+    });
+    if has_uncompressed {
+        Err(Error::ContextError(ScriptContextError::CompressedOnly))
+    } else {
+        Ok(())
+    }
+}
 /// A Segwitv0 wsh descriptor
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub struct Wsh<Pk: MiniscriptKey> {
@@ -167,8 +188,7 @@ impl<Pk: MiniscriptKey> fmt::Debug for Wsh<Pk> {
 impl<Pk: MiniscriptKey> fmt::Display for Wsh<Pk> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let desc = format!("{}{}", ELMTS_STR, self.to_string_no_checksum());
-        let checksum = desc_checksum(&desc).map_err(|_| fmt::Error)?;
-        write!(f, "{}#{}", &desc, &checksum)
+        write_with_checksum(f, &desc)
     }
 }
 
@@ -218,7 +238,7 @@ where
             WshInner::SortedMulti(ref smv) => smv.sanity_check()?,
             WshInner::Ms(ref ms) => ms.sanity_check()?,
         }
-        Ok(())
+        check_compressed_keys(self)
     }
 
     fn address(&self, params: &'static elements::AddressParams) -> Result<elements::Address, Error>
@@ -401,8 +421,7 @@ impl<Pk: MiniscriptKey> fmt::Debug for Wpkh<Pk> {
 impl<Pk: MiniscriptKey> fmt::Display for Wpkh<Pk> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let desc = format!("{}{}", ELMTS_STR, self.to_string_no_checksum());
-        let checksum = desc_checksum(&desc).map_err(|_| fmt::Error)?;
-        write!(f, "{}#{}", &desc, &checksum)
+        write_with_checksum(f, &desc)
     }
 }
 
@@ -524,9 +543,10 @@ where
         Pk: ToPublicKey,
         S: Satisfier<Pk>,
     {
-        if let Some(sig) = satisfier.lookup_sig(&self.pk) {
-            let mut sig_vec = sig.0.serialize_der().to_vec();
-            sig_vec.push(sig.1.as_u32() as u8);
+        let sig = satisfier
+            .lookup_sig(&self.pk)
+            .and_then(|sig| elementssig_to_rawsig_preferred(&satisfier, &sig));
+        if let Some(sig_vec) = sig {
             let script_sig = Script::new();
             let witness = vec![sig_vec, self.pk.to_public_key().to_bytes()];
             Ok((witness, script_sig))
@@ -34,7 +34,7 @@ use {
 };
 
 use super::{
-    checksum::{desc_checksum, verify_checksum},
+    checksum::{verify_checksum, write_with_checksum},
     DescriptorTrait, ElementsTrait, SortedMultiVec, Wpkh, Wsh, ELMTS_STR,
 };
 
@@ -88,8 +88,7 @@ impl<Pk: MiniscriptKey> fmt::Display for Sh<Pk> {
             ShInner::SortedMulti(ref smv) => format!("{}sh({})", ELMTS_STR, smv),
             ShInner::Ms(ref ms) => format!("{}sh({})", ELMTS_STR, ms),
         };
-        let checksum = desc_checksum(&desc).map_err(|_| fmt::Error)?;
-        write!(f, "{}#{}", &desc, &checksum)
+        write_with_checksum(f, &desc)
     }
 }
 
@@ -280,14 +279,8 @@ where
         Pk: ToPublicKey,
     {
         match self.inner {
-            ShInner::Wsh(ref wsh) => {
-                let witness_script = wsh.explicit_script();
-                script::Builder::new()
-                    .push_slice(&witness_script.to_v0_p2wsh()[..])
-                    .into_script()
-            }
-            ShInner::Wpkh(ref wpkh) => {
-                let redeem_script = wpkh.script_pubkey();
+            ShInner::Wsh(..) | ShInner::Wpkh(..) => {
+                let redeem_script = self.redeem_script().expect("Sh has a redeem script");
                 script::Builder::new()
                     .push_slice(&redeem_script[..])
                     .into_script()
@@ -296,6 +289,18 @@ where
         }
     }
 
+    fn redeem_script(&self) -> Option<Script>
+    where
+        Pk: ToPublicKey,
+    {
+        Some(match self.inner {
+            ShInner::Wsh(ref wsh) => wsh.script_pubkey(),
+            ShInner::Wpkh(ref wpkh) => wpkh.script_pubkey(),
+            ShInner::SortedMulti(ref smv) => smv.encode(),
+            ShInner::Ms(ref ms) => ms.encode(),
+        })
+    }
+
     fn explicit_script(&self) -> Script
     where
         Pk: ToPublicKey,
@@ -22,6 +22,9 @@ use Error;
 
 use MAX_RECURSION_DEPTH;
 
+/// Default maximum nesting depth of a [Tree] parsed using [Tree::from_str]
+pub const MAX_TREE_DEPTH: u32 = MAX_RECURSION_DEPTH;
+
 #[derive(Debug, Clone)]
 /// A token of the form `x(...)` or `x`
 pub struct Tree<'a> {
@@ -39,21 +42,48 @@ pub trait FromTree: Sized {
 
 impl<'a> fmt::Display for Tree<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "({}", self.name)?;
-        for arg in &self.args {
-            write!(f, ",{}", arg)?;
+        f.write_str(self.name)?;
+        if !self.args.is_empty() {
+            write!(f, "(")?;
+            for (i, arg) in self.args.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ",")?;
+                }
+                write!(f, "{}", arg)?;
+            }
+            write!(f, ")")?;
         }
-        write!(f, ")")
+        Ok(())
     }
 }
+// Strips the insignificant whitespace around a name, rejecting
+// whitespace inside it
+fn trim_name(name: &str) -> Result<&str, Error> {
+    let name = name.trim();
+    if name.contains(char::is_whitespace) {
+        return Err(Error::BadDescriptor(format!(
+            "Unexpected whitespace inside '{}'",
+            name
+        )));
+    }
+    Ok(name)
+}
+
 impl<'a> Tree<'a> {
-    fn from_slice(sl: &'a str) -> Result<(Tree<'a>, &'a str), Error> {
-        Self::from_slice_helper(sl, 0u32)
+    fn from_slice(sl: &'a str, max_depth: u32) -> Result<(Tree<'a>, &'a str), Error> {
+        Self::from_slice_helper(sl, 0u32, max_depth)
     }
 
-    fn from_slice_helper(mut sl: &'a str, depth: u32) -> Result<(Tree<'a>, &'a str), Error> {
-        if depth >= MAX_RECURSION_DEPTH {
-            return Err(Error::MaxRecursiveDepthExceeded);
+    fn from_slice_helper(
+        mut sl: &'a str,
+        depth: u32,
+        max_depth: u32,
+    ) -> Result<(Tree<'a>, &'a str), Error> {
+        if depth >= max_depth {
+            return Err(Error::BadDescriptor(format!(
+                "nesting too deep: maximum depth is {}",
+                max_depth
+            )));
         }
         enum Found {
             Nothing,
@@ -77,7 +107,7 @@ impl<'a> Tree<'a> {
                     // already inside a key context
                     if key_ctx {
                         key_lparan_count += 1;
-                    } else if &sl[..n] == "slip77" || &sl[..n] == "musig" {
+                    } else if sl[..n].trim() == "slip77" || sl[..n].trim() == "musig" {
                         key_lparan_count = 1;
                         key_ctx = true;
                     } else {
@@ -110,7 +140,7 @@ impl<'a> Tree<'a> {
             // String-ending terminal
             Found::Nothing => Ok((
                 Tree {
-                    name: &sl[..],
+                    name: trim_name(sl)?,
                     args: vec![],
                 },
                 "",
@@ -118,7 +148,7 @@ impl<'a> Tree<'a> {
             // Terminal
             Found::Comma(n) | Found::Rparen(n) => Ok((
                 Tree {
-                    name: &sl[..n],
+                    name: trim_name(&sl[..n])?,
                     args: vec![],
                 },
                 &sl[n..],
@@ -126,14 +156,15 @@ impl<'a> Tree<'a> {
             // Function call
             Found::Lparen(n) => {
                 let mut ret = Tree {
-                    name: &sl[..n],
+                    name: trim_name(&sl[..n])?,
                     args: vec![],
                 };
 
                 sl = &sl[n + 1..];
                 loop {
-                    let (arg, new_sl) = Tree::from_slice_helper(sl, depth + 1)?;
+                    let (arg, new_sl) = Tree::from_slice_helper(sl, depth + 1, max_depth)?;
                     ret.args.push(arg);
+                    let new_sl = new_sl.trim_start();
 
                     if new_sl.is_empty() {
                         return Err(Error::ExpectedChar(')'));
@@ -152,7 +183,16 @@ impl<'a> Tree<'a> {
     }
 
     /// Parses a tree from a string
+    /// Whitespace between tokens is ignored, but not inside them.
+    /// Errors if the tree is nested deeper than [MAX_TREE_DEPTH]
     pub fn from_str(s: &'a str) -> Result<Tree<'a>, Error> {
+        Tree::from_str_with_max_depth(s, MAX_TREE_DEPTH)
+    }
+
+    /// Parses a tree from a string, rejecting trees that are nested
+    /// deeper than `max_depth`. This bounds the recursion when parsing
+    /// untrusted input.
+    pub fn from_str_with_max_depth(s: &'a str, max_depth: u32) -> Result<Tree<'a>, Error> {
         // Filter out non-ASCII because we byte-index strings all over the
         // place and Rust gets very upset when you splinch a string.
         for ch in s.bytes() {
@@ -161,8 +201,8 @@ impl<'a> Tree<'a> {
             }
         }
 
-        let (top, rem) = Tree::from_slice(s)?;
-        if rem.is_empty() {
+        let (top, rem) = Tree::from_slice(s, max_depth)?;
+        if rem.trim().is_empty() {
             Ok(top)
         } else {
             Err(errstr(rem))
@@ -226,10 +266,44 @@ where
     }
 }
 
+/// Annotate a failure to parse the `index`th argument of `term` with its
+/// position, e.g. "while parsing `ct(...)` → arg 1 → ..."
+pub fn arg_error<E: Into<Error>>(term: &Tree, index: usize, e: E) -> Error {
+    e.into()
+        .context(format!("arg {}", index))
+        .context(format!("while parsing `{}(...)`", term.name))
+}
+
 #[cfg(test)]
 mod tests {
 
-    use super::parse_num;
+    use super::{parse_num, Tree, MAX_TREE_DEPTH};
+    use std::str::FromStr;
+    use {Descriptor, DummyKey, Error};
+
+    fn nested(name: &str, depth: usize) -> String {
+        let mut s = String::new();
+        for _ in 0..depth {
+            s.push_str(name);
+            s.push('(');
+        }
+        s.push_str("pk()");
+        for _ in 0..depth {
+            s.push(')');
+        }
+        s
+    }
+
+    #[test]
+    fn whitespace_between_tokens() {
+        let tree = Tree::from_str(" and_v( v:pk(A) ,\n\tolder(1)\n) ").unwrap();
+        assert_eq!(tree.to_string(), "and_v(v:pk(A),older(1))");
+
+        match Tree::from_str("and_v(v:pk(A B),older(1))") {
+            Err(Error::BadDescriptor(e)) => assert!(e.contains("'A B'")),
+            res => panic!("unexpected {:?}", res),
+        }
+    }
 
     #[test]
     fn test_parse_num() {
@@ -240,4 +314,27 @@ mod tests {
         assert!(parse_num("+6").is_err());
         assert!(parse_num("-6").is_err());
     }
+
+    #[test]
+    fn test_max_depth() {
+        let deep = nested("sh", 10_000);
+        match Tree::from_str(&deep) {
+            Err(Error::BadDescriptor(ref e)) => assert!(e.starts_with("nesting too deep")),
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("10000 deep tree must not parse"),
+        }
+        let desc = format!("el{}", deep);
+        match Descriptor::<DummyKey>::from_str(&desc) {
+            Err(Error::BadDescriptor(ref e)) => assert!(e.starts_with("nesting too deep")),
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("10000 deep descriptor must not parse"),
+        }
+
+        // the limit is configurable
+        assert!(Tree::from_str("a(b(c))").is_ok());
+        assert!(Tree::from_str_with_max_depth("a(b(c))", 3).is_ok());
+        assert!(Tree::from_str_with_max_depth("a(b(c))", 2).is_err());
+        let shallow = nested("sh", MAX_TREE_DEPTH as usize - 2);
+        assert!(Tree::from_str(&shallow).is_ok());
+    }
 }
@@ -263,10 +263,45 @@ pub enum Error {
     ImpossibleSatisfaction,
     /// Bare descriptors don't have any addresses
     BareDescriptorAddr,
+    /// The scriptSig contains non-push opcodes, which is non-standard
+    NonPushOnlyScriptSig,
+    /// The script is larger than the standard limit of 10000 bytes
+    StandardScriptSizeExceeded(usize),
+    /// The script has more than the standard limit of 201 non-push opcodes
+    StandardOpCountExceeded(usize),
     /// Upstream Miniscript Errors
     BtcError(bitcoin_miniscript::Error),
     /// Covenant Error
     CovError(descriptor::CovError),
+    /// Pegin Error
+    PeginError(descriptor::pegin::PeginError),
+    /// Interpreter related errors
+    InterpreterError(Box<interpreter::Error>),
+    /// An error annotated with the descriptor fragment being parsed
+    WithContext {
+        /// Where in the descriptor the error occurred
+        fragment: String,
+        /// The underlying error
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Annotate the error with the descriptor fragment being parsed
+    pub fn context<S: ToString>(self, fragment: S) -> Error {
+        Error::WithContext {
+            fragment: fragment.to_string(),
+            source: Box::new(self),
+        }
+    }
+
+    /// The underlying error, with any context stripped off
+    pub fn root(&self) -> &Error {
+        match *self {
+            Error::WithContext { ref source, .. } => source.root(),
+            ref e => e,
+        }
+    }
 }
 
 #[doc(hidden)]
@@ -322,6 +357,13 @@ impl From<elements::secp256k1_zkp::UpstreamError> for Error {
     }
 }
 
+#[doc(hidden)]
+impl From<interpreter::Error> for Error {
+    fn from(e: interpreter::Error) -> Error {
+        Error::InterpreterError(Box::new(e))
+    }
+}
+
 #[doc(hidden)]
 impl From<bitcoin::util::key::Error> for Error {
     fn from(e: bitcoin::util::key::Error) -> Error {
@@ -337,6 +379,8 @@ impl error::Error for Error {
     fn cause(&self) -> Option<&error::Error> {
         match *self {
             Error::BadPubkey(ref e) => Some(e),
+            Error::InterpreterError(ref e) => Some(&**e),
+            Error::WithContext { ref source, .. } => Some(&**source),
             _ => None,
         }
     }
@@ -409,8 +453,27 @@ impl fmt::Display for Error {
             Error::AnalysisError(ref e) => e.fmt(f),
             Error::ImpossibleSatisfaction => write!(f, "Impossible to satisfy Miniscript"),
             Error::BareDescriptorAddr => write!(f, "Bare descriptors don't have address"),
+            Error::NonPushOnlyScriptSig => {
+                write!(f, "Non-standard scriptSig with non-push opcodes")
+            }
+            Error::StandardScriptSizeExceeded(n) => write!(
+                f,
+                "Non-standard script of {} bytes, more than the {} byte limit",
+                n, MAX_SCRIPT_SIZE
+            ),
+            Error::StandardOpCountExceeded(n) => write!(
+                f,
+                "Non-standard script with {} non-push opcodes, more than the limit of 201",
+                n
+            ),
             Error::BtcError(ref e) => write!(f, " Bitcoin Miniscript Error {}", e),
             Error::CovError(ref e) => write!(f, "Covenant Error: {}", e),
+            Error::PeginError(ref e) => write!(f, "Pegin Error: {}", e),
+            Error::InterpreterError(ref e) => write!(f, "Interpreter Error: {}", e),
+            Error::WithContext {
+                ref fragment,
+                ref source,
+            } => write!(f, "{} → {}", fragment, source),
         }
     }
 }
@@ -46,12 +46,38 @@ pub type ElementsSig = (secp256k1_zkp::Signature, elements::SigHashType);
 /// Type alias for 32 byte Preimage.
 pub type Preimage32 = [u8; 32];
 
+/// Serializes an ECDSA signature as pushed in a scriptSig or witness:
+/// DER encoded and followed by the one byte sighash type. Signatures of
+/// any other scheme need their own serialization.
+fn ecdsa_sig_to_rawsig(sig: &secp256k1_zkp::Signature, hashtype: u32) -> Vec<u8> {
+    let mut raw_sig = sig.serialize_der().to_vec();
+    raw_sig.push(hashtype as u8);
+    raw_sig
+}
+
 /// Convert to raw sig
 pub fn elementssig_to_rawsig(sig: &ElementsSig) -> Vec<u8> {
-    let ser_sig = sig.0.serialize_der();
-    let mut raw_sig = Vec::from(&ser_sig[..]);
-    raw_sig.push(sig.1 as u8);
-    raw_sig
+    ecdsa_sig_to_rawsig(&sig.0, sig.1.as_u32())
+}
+
+/// The Elements-specific sighash flag committing to the rangeproofs
+/// of the outputs. `elements::SigHashType` cannot represent it, so it
+/// can only be requested through [Satisfier::preferred_sighash].
+pub const SIGHASH_RANGEPROOF: u32 = 0x40;
+
+/// Convert to raw sig using the sighash type preferred by the satisfier.
+/// Returns `None` if the signature was made with a different sighash type.
+pub fn elementssig_to_rawsig_preferred<Pk, S>(sat: &S, sig: &ElementsSig) -> Option<Vec<u8>>
+where
+    Pk: MiniscriptKey + ToPublicKey,
+    S: Satisfier<Pk>,
+{
+    let hashtype = match sat.preferred_sighash() {
+        Some(ty) if ty & !SIGHASH_RANGEPROOF != sig.1.as_u32() => return None,
+        Some(ty) => ty,
+        None => sig.1.as_u32(),
+    };
+    Some(ecdsa_sig_to_rawsig(&sig.0, hashtype))
 }
 
 /// Helper function to create ElementsSig from Rawsig
@@ -107,6 +133,9 @@ pub trait Satisfier<Pk: MiniscriptKey + ToPublicKey> {
     }
 
     /// Assert whether an relative locktime is satisfied
+    /// The argument is the `older` value required by the script. Implementors
+    /// should check it against the nSequence of the spending input, see
+    /// [Older] for a satisfier doing so.
     fn check_older(&self, _: u32) -> bool {
         false
     }
@@ -116,10 +145,22 @@ pub trait Satisfier<Pk: MiniscriptKey + ToPublicKey> {
         false
     }
 
+    /// The transaction spending the output being satisfied, if known.
+    /// Introspection fragments can only be satisfied when it, or the
+    /// individual items below, are available.
+    fn lookup_tx(&self) -> Option<&elements::Transaction> {
+        None
+    }
+
+    /// The index of the input being satisfied in [Satisfier::lookup_tx]
+    fn lookup_input_index(&self) -> Option<usize> {
+        None
+    }
+
     /// Introspection Data for Covenant support
     /// #1 Version
     fn lookup_nversion(&self) -> Option<u32> {
-        None
+        self.lookup_tx().map(|tx| tx.version)
     }
 
     /// Item 2: hashprevouts
@@ -139,7 +180,10 @@ pub trait Satisfier<Pk: MiniscriptKey + ToPublicKey> {
 
     /// Item 4: outpoint
     fn lookup_outpoint(&self) -> Option<OutPoint> {
-        None
+        let idx = self.lookup_input_index()?;
+        self.lookup_tx()
+            .and_then(|tx| tx.input.get(idx))
+            .map(|txin| txin.previous_output)
     }
 
     /// Item 5: scriptcode
@@ -154,23 +198,35 @@ pub trait Satisfier<Pk: MiniscriptKey + ToPublicKey> {
 
     /// Item 7: sequence
     fn lookup_nsequence(&self) -> Option<u32> {
-        None
+        let idx = self.lookup_input_index()?;
+        self.lookup_tx()
+            .and_then(|tx| tx.input.get(idx))
+            .map(|txin| txin.sequence)
     }
 
     /// Item 8: hashoutputs
     fn lookup_outputs(&self) -> Option<&[elements::TxOut]> {
-        None
+        self.lookup_tx().map(|tx| &tx.output[..])
     }
 
     /// Item 9: nlocktime
     fn lookup_nlocktime(&self) -> Option<u32> {
-        None
+        self.lookup_tx().map(|tx| tx.lock_time)
     }
 
     /// Item 10: sighash type as u32
     fn lookup_sighashu32(&self) -> Option<u32> {
         None
     }
+
+    /// The sighash type signatures must be made with, as the one byte
+    /// serialized type. Signatures with any other type are skipped
+    /// during satisfaction. The type may include [SIGHASH_RANGEPROOF],
+    /// in which case signatures are expected to be made with it and
+    /// their `elements::SigHashType` holds the remaining flags.
+    fn preferred_sighash(&self) -> Option<u32> {
+        None
+    }
 }
 
 // Allow use of `()` as a "no conditions available" satisfier
@@ -277,6 +333,14 @@ impl<'a, Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for &'
         (**self).check_after(t)
     }
 
+    fn lookup_tx(&self) -> Option<&elements::Transaction> {
+        (**self).lookup_tx()
+    }
+
+    fn lookup_input_index(&self) -> Option<usize> {
+        (**self).lookup_input_index()
+    }
+
     fn lookup_nversion(&self) -> Option<u32> {
         (**self).lookup_nversion()
     }
@@ -320,6 +384,10 @@ impl<'a, Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for &'
     fn lookup_sighashu32(&self) -> Option<u32> {
         (**self).lookup_sighashu32()
     }
+
+    fn preferred_sighash(&self) -> Option<u32> {
+        (**self).preferred_sighash()
+    }
 }
 
 impl<'a, Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for &'a mut S {
@@ -359,6 +427,14 @@ impl<'a, Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for &'
         (**self).check_after(t)
     }
 
+    fn lookup_tx(&self) -> Option<&elements::Transaction> {
+        (**self).lookup_tx()
+    }
+
+    fn lookup_input_index(&self) -> Option<usize> {
+        (**self).lookup_input_index()
+    }
+
     fn lookup_nversion(&self) -> Option<u32> {
         (**self).lookup_nversion()
     }
@@ -402,6 +478,10 @@ impl<'a, Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for &'
     fn lookup_sighashu32(&self) -> Option<u32> {
         (**self).lookup_sighashu32()
     }
+
+    fn preferred_sighash(&self) -> Option<u32> {
+        (**self).preferred_sighash()
+    }
 }
 
 macro_rules! impl_tuple_satisfier {
@@ -508,6 +588,26 @@ macro_rules! impl_tuple_satisfier {
                 false
             }
 
+            fn lookup_tx(&self) -> Option<&elements::Transaction> {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if let Some(result) = $ty.lookup_tx() {
+                        return Some(result);
+                    }
+                )*
+                None
+            }
+
+            fn lookup_input_index(&self) -> Option<usize> {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if let Some(result) = $ty.lookup_input_index() {
+                        return Some(result);
+                    }
+                )*
+                None
+            }
+
             fn lookup_nversion(&self) -> Option<u32> {
                 let &($(ref $ty,)*) = self;
                 $(
@@ -617,6 +717,16 @@ macro_rules! impl_tuple_satisfier {
                 )*
                 None
             }
+
+            fn preferred_sighash(&self) -> Option<u32> {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if let Some(result) = $ty.preferred_sighash() {
+                        return Some(result);
+                    }
+                )*
+                None
+            }
         }
     }
 }
@@ -670,12 +780,11 @@ impl Ord for Witness {
 impl Witness {
     /// Turn a signature into (part of) a satisfaction
     fn signature<Pk: ToPublicKey, S: Satisfier<Pk>>(sat: S, pk: &Pk) -> Self {
-        match sat.lookup_sig(pk) {
-            Some((sig, hashtype)) => {
-                let mut ret = sig.serialize_der().to_vec();
-                ret.push(hashtype.as_u32() as u8);
-                Witness::Stack(vec![ret])
-            }
+        match sat
+            .lookup_sig(pk)
+            .and_then(|sig| elementssig_to_rawsig_preferred(&sat, &sig))
+        {
+            Some(sig) => Witness::Stack(vec![sig]),
             // Signatures cannot be forged
             None => Witness::Impossible,
         }
@@ -693,12 +802,11 @@ impl Witness {
 
     /// Turn a key/signature pair related to a pkh into (part of) a satisfaction
     fn pkh_signature<Pk: ToPublicKey, S: Satisfier<Pk>>(sat: S, pkh: &Pk::Hash) -> Self {
-        match sat.lookup_pkh_sig(pkh) {
-            Some((pk, (sig, hashtype))) => {
-                let mut ret = sig.serialize_der().to_vec();
-                ret.push(hashtype.as_u32() as u8);
-                Witness::Stack(vec![ret.to_vec(), pk.to_public_key().to_bytes()])
-            }
+        let sig = sat
+            .lookup_pkh_sig(pkh)
+            .and_then(|(pk, sig)| elementssig_to_rawsig_preferred(&sat, &sig).map(|sig| (pk, sig)));
+        match sig {
+            Some((pk, sig)) => Witness::Stack(vec![sig, pk.to_public_key().to_bytes()]),
             None => Witness::Impossible,
         }
     }
@@ -34,12 +34,11 @@ use descriptor::Descriptor;
 use miniscript::{Miniscript, ScriptContext};
 use Terminal;
 
-use descriptor::CovError;
-
 pub use self::concrete::Policy as Concrete;
 /// Semantic policies are "abstract" policies elsewhere; but we
 /// avoid this word because it is a reserved keyword in Rust
 pub use self::semantic::Policy as Semantic;
+pub use self::semantic::{CovenantPredicate, SpendingPath};
 use Error;
 use MiniscriptKey;
 
@@ -61,6 +60,12 @@ const ENTAILMENT_MAX_TERMINALS: usize = 20;
 pub trait Liftable<Pk: MiniscriptKey> {
     /// Convert the object into an abstract policy
     fn lift(&self) -> Result<Semantic<Pk>, Error>;
+
+    /// The distinct ways of spending the object, see
+    /// [Semantic::spending_paths]
+    fn spending_paths(&self) -> Result<Vec<SpendingPath<Pk>>, Error> {
+        Ok(self.lift()?.spending_paths())
+    }
 }
 
 /// Detailed Error type for Policies
@@ -137,7 +142,10 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Liftable<Pk> for Terminal<Pk, Ctx> {
             Terminal::Hash160(h) => Semantic::Hash160(h),
             Terminal::True => Semantic::Trivial,
             Terminal::False => Semantic::Unsatisfiable,
-            Terminal::Version(_) | Terminal::OutputsPref(_) => return Err(CovError::CovenantLift)?,
+            Terminal::Version(n) => Semantic::Covenant(CovenantPredicate::Version(n)),
+            Terminal::OutputsPref(ref pref) => {
+                Semantic::Covenant(CovenantPredicate::OutputsPref(pref.clone()))
+            }
             Terminal::Alt(ref sub)
             | Terminal::Swap(ref sub)
             | Terminal::Check(ref sub)
@@ -186,7 +194,7 @@ impl<Pk: MiniscriptKey> Liftable<Pk> for Descriptor<Pk> {
             Descriptor::Wpkh(ref wpkh) => wpkh.lift(),
             Descriptor::Wsh(ref wsh) => wsh.lift(),
             Descriptor::Sh(ref sh) => sh.lift(),
-            Descriptor::Cov(ref _cov) => Err(Error::CovError(CovError::CovenantLift)),
+            Descriptor::Cov(ref cov) => cov.lift(),
         }
     }
 }
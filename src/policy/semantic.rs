@@ -17,7 +17,7 @@
 use std::str::FromStr;
 use std::{fmt, str};
 
-use elements::hashes::hex::FromHex;
+use elements::hashes::hex::{FromHex, ToHex};
 use elements::hashes::{hash160, ripemd160, sha256, sha256d};
 
 use super::concrete::PolicyError;
@@ -53,10 +53,35 @@ pub enum Policy<Pk: MiniscriptKey> {
     Ripemd160(ripemd160::Hash),
     /// A HASH160 whose preimage must be provided to satisfy the descriptor
     Hash160(hash160::Hash),
+    /// A covenant restriction on the spending transaction
+    Covenant(CovenantPredicate),
     /// A set of descriptors, satisfactions must be provided for `k` of them
     Threshold(usize, Vec<Policy<Pk>>),
 }
 
+/// Restrictions on the spending transaction enforced by covenant
+/// fragments. These are satisfied by the transaction itself rather
+/// than by any witness data supplied by the spender.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum CovenantPredicate {
+    /// The transaction version must be equal to the given value
+    Version(u32),
+    /// The serialized outputs of the transaction must start with
+    /// the given prefix
+    OutputsPref(Vec<u8>),
+}
+
+impl fmt::Display for CovenantPredicate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CovenantPredicate::Version(n) => write!(f, "ver_eq({})", n),
+            CovenantPredicate::OutputsPref(ref pref) => {
+                write!(f, "outputs_pref({})", pref.to_hex())
+            }
+        }
+    }
+}
+
 impl<Pk: MiniscriptKey> ForEachKey<Pk> for Policy<Pk> {
     fn for_each_key<'a, F: FnMut(ForEach<'a, Pk>) -> bool>(&'a self, mut pred: F) -> bool
     where
@@ -71,7 +96,8 @@ impl<Pk: MiniscriptKey> ForEachKey<Pk> for Policy<Pk> {
             | Policy::Ripemd160(..)
             | Policy::Hash160(..)
             | Policy::After(..)
-            | Policy::Older(..) => true,
+            | Policy::Older(..)
+            | Policy::Covenant(..) => true,
             Policy::Threshold(_, ref subs) => subs.iter().all(|sub| sub.for_each_key(&mut pred)),
         }
     }
@@ -95,6 +121,7 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
             Policy::Hash160(ref h) => Ok(Policy::Hash160(h.clone())),
             Policy::After(n) => Ok(Policy::After(n)),
             Policy::Older(n) => Ok(Policy::Older(n)),
+            Policy::Covenant(ref c) => Ok(Policy::Covenant(c.clone())),
             Policy::Threshold(k, ref subs) => {
                 let new_subs: Result<Vec<Policy<Q>>, _> = subs
                     .iter()
@@ -202,6 +229,7 @@ impl<Pk: MiniscriptKey> fmt::Debug for Policy<Pk> {
             Policy::Hash256(h) => write!(f, "hash256({})", h),
             Policy::Ripemd160(h) => write!(f, "ripemd160({})", h),
             Policy::Hash160(h) => write!(f, "hash160({})", h),
+            Policy::Covenant(ref c) => write!(f, "{}", c),
             Policy::Threshold(k, ref subs) => {
                 if k == subs.len() {
                     write!(f, "and(")?;
@@ -235,6 +263,7 @@ impl<Pk: MiniscriptKey> fmt::Display for Policy<Pk> {
             Policy::Hash256(h) => write!(f, "hash256({})", h),
             Policy::Ripemd160(h) => write!(f, "ripemd160({})", h),
             Policy::Hash160(h) => write!(f, "hash160({})", h),
+            Policy::Covenant(ref c) => write!(f, "{}", c),
             Policy::Threshold(k, ref subs) => {
                 if k == subs.len() {
                     write!(f, "and(")?;
@@ -311,6 +340,12 @@ where
             ("hash160", 1) => expression::terminal(&top.args[0], |x| {
                 hash160::Hash::from_hex(x).map(Policy::Hash160)
             }),
+            ("ver_eq", 1) => expression::terminal(&top.args[0], |x| {
+                expression::parse_num(x).map(|n| Policy::Covenant(CovenantPredicate::Version(n)))
+            }),
+            ("outputs_pref", 1) => expression::terminal(&top.args[0], |x| {
+                Vec::<u8>::from_hex(x).map(|p| Policy::Covenant(CovenantPredicate::OutputsPref(p)))
+            }),
             ("and", nsubs) => {
                 if nsubs < 2 {
                     return Err(Error::PolicyError(PolicyError::InsufficientArgsforAnd));
@@ -453,7 +488,8 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
             | Policy::Sha256(..)
             | Policy::Hash256(..)
             | Policy::Ripemd160(..)
-            | Policy::Hash160(..) => vec![],
+            | Policy::Hash160(..)
+            | Policy::Covenant(..) => vec![],
             Policy::After(..) => vec![],
             Policy::Older(t) => vec![t],
             Policy::Threshold(_, ref subs) => subs.iter().fold(vec![], |mut acc, x| {
@@ -502,7 +538,8 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
             | Policy::Sha256(..)
             | Policy::Hash256(..)
             | Policy::Ripemd160(..)
-            | Policy::Hash160(..) => 0,
+            | Policy::Hash160(..)
+            | Policy::Covenant(..) => 0,
             Policy::Threshold(_, ref subs) => subs.iter().map(|sub| sub.n_keys()).sum::<usize>(),
         }
     }
@@ -518,7 +555,8 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
             | Policy::Sha256(..)
             | Policy::Hash256(..)
             | Policy::Ripemd160(..)
-            | Policy::Hash160(..) => 0,
+            | Policy::Hash160(..)
+            | Policy::Covenant(..) => 0,
             Policy::Threshold(k, ref subs) => {
                 let mut sublens: Vec<usize> = subs.iter().map(Policy::minimum_n_keys).collect();
                 sublens.sort();
@@ -545,6 +583,58 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
     }
 }
 
+/// One way of spending a policy, as returned by [Policy::spending_paths].
+/// All of its conditions must be met. Each condition is a key, timelock,
+/// hash preimage or covenant requirement, or a `k`-of-`n` threshold
+/// (with `1 < k < n`) of such conditions.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct SpendingPath<Pk: MiniscriptKey> {
+    /// The conditions required by this path
+    pub conditions: Vec<Policy<Pk>>,
+}
+
+impl<Pk: MiniscriptKey> Policy<Pk> {
+    /// Enumerates the distinct ways of spending the policy, expanding
+    /// every disjunction, including those nested under conjunctions.
+    /// Thresholds which are neither are kept as a single condition,
+    /// so that e.g. a 11-of-15 multisig is one condition rather than
+    /// every subset of 11 keys. An unsatisfiable policy has no paths.
+    pub fn spending_paths(&self) -> Vec<SpendingPath<Pk>> {
+        self.clone()
+            .normalized()
+            .condition_sets()
+            .into_iter()
+            .map(|conditions| SpendingPath { conditions })
+            .collect()
+    }
+
+    // The sets of conditions of the spending paths of a normalized policy
+    fn condition_sets(&self) -> Vec<Vec<Policy<Pk>>> {
+        match *self {
+            Policy::Unsatisfiable => vec![],
+            Policy::Trivial => vec![vec![]],
+            Policy::Threshold(1, ref subs) => {
+                subs.iter().flat_map(Policy::condition_sets).collect()
+            }
+            Policy::Threshold(k, ref subs) if k == subs.len() => {
+                subs.iter().fold(vec![vec![]], |sets, sub| {
+                    let sub_sets = sub.condition_sets();
+                    sets.iter()
+                        .flat_map(|set| {
+                            sub_sets.iter().map(move |sub_set| {
+                                let mut set = set.clone();
+                                set.extend(sub_set.iter().cloned());
+                                set
+                            })
+                        })
+                        .collect()
+                })
+            }
+            ref x => vec![vec![x.clone()]],
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use bitcoin::PublicKey;
@@ -572,6 +662,44 @@ mod tests {
         .is_ok());
     }
 
+    #[test]
+    fn spending_paths() {
+        let policy = StringPolicy::from_str(
+            "or(and(pkh(A),or(older(10),sha256(1111111111111111111111111111111111111111111111111111111111111111))),thresh(2,pkh(B),pkh(C),pkh(D)))",
+        )
+        .unwrap();
+        let hash = sha256::Hash::from_hex(
+            "1111111111111111111111111111111111111111111111111111111111111111",
+        )
+        .unwrap();
+        let paths: Vec<_> = policy
+            .spending_paths()
+            .into_iter()
+            .map(|path| path.conditions)
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                vec![Policy::KeyHash("A".to_owned()), Policy::Older(10)],
+                vec![Policy::KeyHash("A".to_owned()), Policy::Sha256(hash)],
+                vec![Policy::Threshold(
+                    2,
+                    vec![
+                        Policy::KeyHash("B".to_owned()),
+                        Policy::KeyHash("C".to_owned()),
+                        Policy::KeyHash("D".to_owned()),
+                    ]
+                )],
+            ]
+        );
+
+        assert!(Policy::<String>::Unsatisfiable.spending_paths().is_empty());
+        assert_eq!(
+            Policy::<String>::Trivial.spending_paths(),
+            vec![SpendingPath { conditions: vec![] }]
+        );
+    }
+
     #[test]
     fn semantic_analysis() {
         let policy = StringPolicy::from_str("pkh()").unwrap();